@@ -72,7 +72,7 @@ fn setup_search(
 
     let words = setup_search_words(rng, size_words, word_vocabulary);
     let tags = setup_search_tags(rng, size_tags, tag_vocabulary);
-    SearchParams { words, tags }
+    SearchParams::new(words, tags)
 }
 
 /// Create a random list of words to search for.
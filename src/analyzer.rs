@@ -0,0 +1,189 @@
+use crate::*;
+use rust_stemmers::Stemmer;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+pub use rust_stemmers::Algorithm;
+
+/// Common English words that carry little meaning in a search.
+pub const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "that", "the", "to", "was", "with",
+];
+
+/// Turns text into the terms of the word index.
+///
+/// Text is tokenized, stop words are dropped, and the remaining words are optionally stemmed.
+/// Searched terms are followed by their synonyms, so that the index only holds
+/// the terms of the items.
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+    tokenizer: Arc<dyn Tokenizer>,
+    stemming: Option<Algorithm>,
+    stop_words: HashSet<String>,
+
+    /// Groups of interchangeable words, as given.
+    synonym_groups: Vec<Vec<String>>,
+
+    /// Map of term to its synonymous terms, derived from the synonym groups.
+    synonyms: HashMap<String, Vec<String>>,
+}
+
+impl Analyzer {
+    pub fn new() -> Analyzer {
+        Analyzer {
+            tokenizer: Arc::new(UnicodeTokenizer::new()),
+            stemming: None,
+            stop_words: HashSet::new(),
+            synonym_groups: vec![],
+            synonyms: HashMap::new(),
+        }
+    }
+
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Analyzer {
+        self.tokenizer = Arc::new(tokenizer);
+        self
+    }
+
+    /// Stems words using the given language's algorithm.
+    pub fn with_stemming(mut self, algorithm: Algorithm) -> Analyzer {
+        self.stemming = Some(algorithm);
+        self.build_synonyms();
+        self
+    }
+
+    /// Ignores the given words.
+    pub fn with_stop_words(mut self, words: &[&str]) -> Analyzer {
+        self.stop_words
+            .extend(words.iter().map(|word| word.to_string()));
+        self
+    }
+
+    /// Treats the given words as interchangeable.
+    pub fn with_synonyms(mut self, words: &[&str]) -> Analyzer {
+        self.synonym_groups
+            .push(words.iter().map(|word| word.to_string()).collect());
+        self.build_synonyms();
+        self
+    }
+
+    pub fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
+    /// # Returns
+    /// Whether every word of a text is one of its terms, as written.
+    pub fn preserves_words(&self) -> bool {
+        self.stemming.is_none() && self.stop_words.is_empty()
+    }
+
+    /// # Returns
+    /// Terms of the text, in order of appearance.
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.analyze_positions(text)
+            .into_iter()
+            .map(|(_, term)| term)
+            .collect()
+    }
+
+    /// # Returns
+    /// Terms of the text, in order of appearance, with their word offset.
+    /// Stop words keep their position.
+    pub fn analyze_positions(&self, text: &str) -> Vec<(usize, String)> {
+        self.analyze_words(text)
+            .into_iter()
+            .map(|(position, _, term)| (position, term))
+            .collect()
+    }
+
+    /// # Returns
+    /// Terms of the text, in order of appearance, with their word offset
+    /// and the word of the text they stem from.
+    pub fn analyze_words<'a>(&self, text: &'a str) -> Vec<(usize, &'a str, String)> {
+        self.analyze_tokens(self.tokenizer.tokenize_positions(text), false)
+    }
+
+    /// # Returns
+    /// Terms of a searched text, each followed by its synonyms,
+    /// tokenized not to match more than the text itself.
+    pub fn analyze_query(&self, text: &str) -> Vec<String> {
+        let tokens = self.tokenizer.tokenize_query(text).into_iter().enumerate();
+        self.analyze_tokens(tokens.collect(), true)
+            .into_iter()
+            .map(|(_, _, term)| term)
+            .collect()
+    }
+
+    /// # Returns
+    /// Terms of a searched phrase, with their word offset.
+    /// Synonyms share the position of their term.
+    pub fn analyze_phrase(&self, text: &str) -> Vec<(usize, String)> {
+        self.analyze_tokens(self.tokenizer.tokenize_positions(text), true)
+            .into_iter()
+            .map(|(position, _, term)| (position, term))
+            .collect()
+    }
+
+    fn analyze_tokens<'a>(
+        &self,
+        tokens: Vec<(usize, &'a str)>,
+        with_synonyms: bool,
+    ) -> Vec<(usize, &'a str, String)> {
+        let stemmer = self.stemming.map(Stemmer::create);
+        let mut terms = vec![];
+        for (position, word) in tokens {
+            if self.stop_words.contains(word) {
+                continue;
+            }
+
+            let term = stem(stemmer.as_ref(), word);
+            let synonyms = match self.synonyms.get(&term) {
+                Some(synonyms) if with_synonyms => synonyms.as_slice(),
+                _ => &[],
+            };
+
+            terms.push((position, word, term));
+            terms.extend(
+                synonyms
+                    .iter()
+                    .map(|synonym| (position, word, synonym.clone())),
+            );
+        }
+
+        terms
+    }
+
+    /// Rebuilds the synonym map from the synonym groups.
+    fn build_synonyms(&mut self) {
+        let stemmer = self.stemming.map(Stemmer::create);
+        self.synonyms.clear();
+        for group in self.synonym_groups.iter() {
+            let terms = group
+                .iter()
+                .map(|word| stem(stemmer.as_ref(), word))
+                .collect::<Vec<_>>();
+
+            for term in terms.iter() {
+                let synonyms = self.synonyms.entry(term.clone()).or_insert(vec![]);
+                for synonym in terms.iter() {
+                    if synonym != term && !synonyms.contains(synonym) {
+                        synonyms.push(synonym.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn stem(stemmer: Option<&Stemmer>, word: &str) -> String {
+    match stemmer {
+        Some(stemmer) => stemmer.stem(word).into_owned(),
+        None => word.to_string(),
+    }
+}
@@ -2,16 +2,112 @@ extern crate todo_swamp;
 
 use std::io;
 use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::process;
 
 use todo_swamp::*;
 
+/// Where `serve` listens.
+enum Address {
+    /// Port on localhost.
+    Tcp(u16),
+
+    /// Port on localhost, serving the HTTP API.
+    Http(u16),
+
+    #[cfg(unix)]
+    Unix(String),
+}
+
+/// Reads lines from stdin, or with `serve --tcp PORT` or `serve --unix PATH`,
+/// from the clients connecting to a localhost port or Unix socket.
+/// `serve --http PORT` serves the HTTP API on a localhost port instead.
 pub fn main() {
     let mut tl: TodoList = TodoList::new();
+    let mut options = runner::Options {
+        color: io::stdout().is_terminal(),
+        ..runner::Options::default()
+    };
+
+    let mut serve = false;
+    let mut address = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "serve" => serve = true,
+            "--tcp" => {
+                let port = args.next().unwrap_or_default();
+                let port = port
+                    .parse()
+                    .unwrap_or_else(|_| exit("--tcp expects a port"));
+                address = Some(Address::Tcp(port));
+            }
+            "--http" => {
+                let port = args.next().unwrap_or_default();
+                let port = port
+                    .parse()
+                    .unwrap_or_else(|_| exit("--http expects a port"));
+                address = Some(Address::Http(port));
+            }
+            #[cfg(unix)]
+            "--unix" => {
+                let path = args.next().unwrap_or_else(|| exit("--unix expects a path"));
+                address = Some(Address::Unix(path));
+            }
+            "--format" => {
+                let format = args.next().unwrap_or_default();
+                options.format = format.parse().unwrap_or_else(|e: String| exit(&e));
+            }
+            _ => match arg.strip_prefix("--format=") {
+                Some(format) => {
+                    options.format = format.parse().unwrap_or_else(|e: String| exit(&e));
+                }
+                None => exit(&format!("unknown argument {}", arg)),
+            },
+        }
+    }
 
+    if serve {
+        options.color = false;
+        let tl = SharedTodoList::new(tl);
+        let served = match address {
+            Some(Address::Tcp(port)) => TcpListener::bind(("127.0.0.1", port))
+                .and_then(|listener| server::serve_tcp(listener, tl, options)),
+            Some(Address::Http(port)) => TcpListener::bind(("127.0.0.1", port))
+                .and_then(|listener| http::serve_http(listener, tl)),
+            #[cfg(unix)]
+            Some(Address::Unix(path)) => UnixListener::bind(path)
+                .and_then(|listener| server::serve_unix(listener, tl, options)),
+            None => exit("serve expects --tcp PORT, --http PORT or --unix PATH"),
+        };
+
+        if let Err(e) = served {
+            exit(&e.to_string());
+        }
+
+        return;
+    }
+
+    let mut session = runner::Session::new();
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
         if let Ok(l) = line {
-            runner::run_line(&l, &mut tl, &mut io::stdout(), &mut io::stderr());
+            runner::run_session_line(
+                &l,
+                &mut tl,
+                &mut session,
+                &mut io::stdout(),
+                &mut io::stderr(),
+                options,
+            );
         }
     }
 }
+
+fn exit(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    process::exit(2)
+}
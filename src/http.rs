@@ -0,0 +1,328 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::runner;
+use crate::*;
+
+/// Longest request body accepted.
+const MAX_BODY: usize = 1 << 20;
+
+/// Interval at which subscribers check for new changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of polls without changes after which subscribers are sent a comment,
+/// so that closed connections are noticed.
+const KEEP_ALIVE_POLLS: u32 = 150;
+
+/// Serves the list over HTTP, one request per connection, each on its own thread,
+/// until accepting a connection fails:
+///
+/// - `POST /items` with `{"description": "buy milk", "tags": ["groceries"]}` adds an item.
+/// - `POST /items/{index}/done` marks the item done.
+/// - `GET /items?q=...&tag=...` searches with the words of `q`, in `search` syntax,
+///   or any of the tags.
+/// - `GET /items/{index}` gets the item.
+/// - `GET /events` streams the changes applied from then on as server-sent events,
+///   or from after the change numbered by the `Last-Event-ID` header or `since` parameter.
+pub fn serve_http(listener: TcpListener, tl: SharedTodoList) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        let tl = tl.clone();
+        thread::spawn(move || serve_client(stream, &tl));
+    }
+}
+
+fn serve_client(stream: TcpStream, tl: &SharedTodoList) {
+    let (status, body) = match stream.try_clone().map(BufReader::new) {
+        Ok(mut reader) => match read_request(&mut reader) {
+            Ok(request) if request.method == "GET" && request.path == "/events" => {
+                let _ = stream_events(stream, &request, tl);
+                return;
+            }
+            Ok(request) => respond(&request, tl),
+            Err(e) => (400, error(&e.to_string())),
+        },
+        Err(_) => return,
+    };
+
+    let _ = write_response(stream, status, &body);
+}
+
+/// An HTTP request.
+struct Request {
+    method: String,
+
+    /// Path without the query string.
+    path: String,
+
+    /// Decoded query string parameters, in order.
+    params: Vec<(String, String)>,
+
+    last_event_id: Option<String>,
+
+    body: Vec<u8>,
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let mut length = 0;
+    let mut last_event_id = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(invalid("unexpected end of headers"));
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("malformed content length"))?;
+            } else if name.eq_ignore_ascii_case("last-event-id") {
+                last_event_id = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let params = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (decode(key), decode(value))
+        })
+        .collect();
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        params,
+        last_event_id,
+        body,
+    })
+}
+
+/// Writes the changes applied after the requested one as they are applied.
+/// A `reset` event carrying the number of the last change is sent instead
+/// when some of the requested changes are no longer kept.
+fn stream_events(mut stream: TcpStream, request: &Request, tl: &SharedTodoList) -> io::Result<()> {
+    let since = request
+        .params
+        .iter()
+        .find(|(key, _)| key == "since")
+        .map(|(_, value)| value.as_str())
+        .or(request.last_event_id.as_deref());
+
+    let mut seq = match since.map(str::parse) {
+        Some(Ok(seq)) => seq,
+        Some(Err(_)) => return write_response(stream, 400, &error("invalid event id")),
+        None => tl.read(TodoList::last_change),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+    )?;
+
+    let mut idle = 0;
+    loop {
+        let changes = tl.read(|tl| tl.changes_since(seq).ok_or_else(|| tl.last_change()));
+
+        match changes {
+            Ok(changes) if changes.is_empty() => {
+                idle += 1;
+                if idle == KEEP_ALIVE_POLLS {
+                    write!(stream, ":\n\n")?;
+                    idle = 0;
+                }
+
+                stream.flush()?;
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Ok(changes) => {
+                for change in changes {
+                    let event = json::change_kind(change.kind);
+                    let data = json::change(&change);
+                    write!(
+                        stream,
+                        "id: {}\nevent: {}\ndata: {}\n\n",
+                        change.seq, event, data
+                    )?;
+                    seq = change.seq;
+                }
+            }
+            Err(last) => {
+                let data = json!({ "seq": last });
+                write!(stream, "id: {}\nevent: reset\ndata: {}\n\n", last, data)?;
+                seq = last;
+            }
+        }
+
+        idle = 0;
+        stream.flush()?;
+    }
+}
+
+/// Decodes `+` and `%XX` escapes of a query string parameter.
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// # Returns
+/// Status code and JSON body answering the request.
+fn respond(request: &Request, tl: &SharedTodoList) -> (u16, Value) {
+    let segments = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+
+    let index = |segment: &str| segment.parse().ok().map(Index::new);
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["items"]) => {
+            let params: Value = match serde_json::from_slice(&request.body) {
+                Ok(params) => params,
+                Err(e) => return (400, error(&e.to_string())),
+            };
+
+            match json::query("add", &params) {
+                Ok(q) => run(q, tl, 201),
+                Err(e) => (400, error(&e.message)),
+            }
+        }
+        ("POST", ["items", i, "done"]) => match index(i) {
+            Some(idx) => run(Query::Done(idx), tl, 200),
+            None => not_found(),
+        },
+        ("GET", ["items"]) => match search_params(request, tl) {
+            Ok(params) => run(Query::Search(params), tl, 200),
+            Err(message) => (400, error(&message)),
+        },
+        ("GET", ["items", i]) => match index(i).and_then(|idx| tl.get(idx)) {
+            Some(item) => (200, json::item(&item)),
+            None => not_found(),
+        },
+        (_, ["items"]) | (_, ["items", _]) | (_, ["items", _, "done"]) => {
+            (405, error("method not allowed"))
+        }
+        _ => not_found(),
+    }
+}
+
+/// Reads the search words of the `q` parameters, and the tags of the `tag` parameters.
+fn search_params(request: &Request, tl: &SharedTodoList) -> Result<SearchParams, String> {
+    let mut params = SearchParams::new(vec![], vec![]);
+    for (key, value) in request.params.iter() {
+        match key.as_str() {
+            "q" => {
+                let line = format!("search {}", value);
+                let parsed = tl.read(|tl| parser::query_with(&line, tl.tokenizer()).ok());
+                match parsed {
+                    Some(("", Query::Search(search))) => {
+                        params.words.extend(search.words);
+                        params.tags.extend(search.tags);
+                        params.phrases.extend(search.phrases);
+                        params.regexes.extend(search.regexes);
+                        params.tag_patterns.extend(search.tag_patterns);
+                    }
+                    _ => return Err(format!("invalid search {}", value)),
+                }
+            }
+            "tag" => params.tags.push(Tag::new(value.trim_start_matches('#'))),
+            _ => return Err(format!("unknown parameter {}", key)),
+        }
+    }
+
+    Ok(params)
+}
+
+/// Runs the query, answering errors of the list, such as marking a missing item done, with 404.
+fn run(q: Query, tl: &SharedTodoList, status: u16) -> (u16, Value) {
+    match runner::run_shared_query(q, tl) {
+        Ok(result) => (status, json::result(&result)),
+        Err(e) => (404, json::error(&e)),
+    }
+}
+
+fn not_found() -> (u16, Value) {
+    (404, error("not found"))
+}
+
+fn error(message: &str) -> Value {
+    json!({ "type": "error", "message": message })
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+
+    stream.flush()
+}
@@ -0,0 +1,398 @@
+use serde_json::{json, Value};
+
+use crate::*;
+
+/// # Returns
+/// The item as a JSON object, with tags without their `#`.
+pub fn item(item: &TodoItem) -> Value {
+    json!({
+        "index": item.index.value(),
+        "description": item.description.value(),
+        "tags": item.tags.iter().map(Tag::value).collect::<Vec<_>>(),
+        "done": item.done,
+    })
+}
+
+/// # Returns
+/// The change as a JSON object whose `type` field names the kind of change.
+pub fn change(change: &Change) -> Value {
+    json!({ "seq": change.seq, "type": change_kind(change.kind), "item": item(&change.item) })
+}
+
+pub fn change_kind(kind: ChangeKind) -> &'static str {
+    match kind {
+        ChangeKind::Added => "added",
+        ChangeKind::Done => "done",
+        ChangeKind::Edited => "edited",
+    }
+}
+
+/// # Returns
+/// The result as a JSON object whose `type` field names the kind of result.
+pub fn result(result: &QueryResult) -> Value {
+    match result {
+        QueryResult::Added(i) => json!({ "type": "added", "item": item(i) }),
+        QueryResult::Done => json!({ "type": "done" }),
+        QueryResult::Found {
+            items,
+            facets,
+            explanations,
+            highlights,
+            suggestions,
+        } => {
+            let mut sorted = items.iter().collect::<Vec<_>>();
+            sorted.sort_by_key(|i| std::cmp::Reverse(i.index));
+
+            let mut found = json!({
+                "type": "found",
+                "items": sorted.into_iter().map(item).collect::<Vec<_>>(),
+                "suggestions": suggestions,
+            });
+
+            if let Some(facets) = facets {
+                found["facets"] = tag_counts(facets);
+            }
+
+            if let Some(explanations) = explanations {
+                found["explanations"] = explanations
+                    .iter()
+                    .map(|explanation| {
+                        let hits = explanation
+                            .hits
+                            .iter()
+                            .map(|hit| json!({ "term": hit.term, "matched": hit.matched }))
+                            .collect::<Vec<_>>();
+
+                        json!({ "index": explanation.index.value(), "hits": hits })
+                    })
+                    .collect();
+            }
+
+            if let Some(highlights) = highlights {
+                found["highlights"] = highlights
+                    .iter()
+                    .map(|h| json!({ "index": h.index.value(), "ranges": h.ranges }))
+                    .collect();
+            }
+
+            found
+        }
+        QueryResult::Count(n) => json!({ "type": "count", "count": n }),
+        QueryResult::Groups(groups) => json!({ "type": "groups", "groups": tag_counts(groups) }),
+        QueryResult::Tags(nodes) => json!({ "type": "tags", "tags": tag_nodes(nodes) }),
+        QueryResult::Counts(counts) => {
+            let counts = counts
+                .iter()
+                .map(|(term, count)| json!({ "term": term, "count": count }))
+                .collect::<Vec<_>>();
+
+            json!({ "type": "counts", "counts": counts })
+        }
+        QueryResult::Updated(n) => json!({ "type": "updated", "count": n }),
+        QueryResult::Staged(n) => json!({ "type": "staged", "count": n }),
+        QueryResult::Committed(n) => json!({ "type": "committed", "count": n }),
+        QueryResult::RolledBack(n) => json!({ "type": "rolled_back", "count": n }),
+        QueryResult::Rules(rules) => {
+            let rules = rules.iter().map(Rule::to_string).collect::<Vec<_>>();
+            json!({ "type": "rules", "rules": rules })
+        }
+    }
+}
+
+/// # Returns
+/// The error as a JSON object of type `error`.
+pub fn error(error: &QueryError) -> Value {
+    json!({ "type": "error", "message": error.0 })
+}
+
+fn tag_counts(counts: &[(Tag, usize)]) -> Value {
+    counts
+        .iter()
+        .map(|(tag, count)| json!({ "tag": tag.value(), "count": count }))
+        .collect()
+}
+
+fn tag_nodes(nodes: &[TagNode]) -> Value {
+    nodes
+        .iter()
+        .map(|node| {
+            json!({
+                "tag": node.tag.value(),
+                "count": node.count,
+                "children": tag_nodes(&node.children),
+            })
+        })
+        .collect()
+}
+
+/// Error codes of the JSON-RPC 2.0 specification.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Code of the errors returned by the todo list itself.
+const QUERY_ERROR: i64 = 1;
+
+/// Error of a JSON-RPC request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> RpcError {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<QueryError> for RpcError {
+    fn from(error: QueryError) -> Self {
+        RpcError::new(QUERY_ERROR, error.0)
+    }
+}
+
+/// Decodes a JSON-RPC request, such as
+/// `{"jsonrpc": "2.0", "id": 1, "method": "add", "params": {"description": "buy milk"}}`.
+///
+/// # Returns
+/// Id of the request, `null` if it could not be read, and its query.
+pub fn request(line: &str) -> (Value, Result<Query, RpcError>) {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return (Value::Null, Err(RpcError::new(PARSE_ERROR, e))),
+    };
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = match request.get("method").and_then(Value::as_str) {
+        Some(method) => method,
+        None => return (id, Err(RpcError::new(INVALID_REQUEST, "missing method"))),
+    };
+
+    let no_params = json!({});
+    let params = request.get("params").unwrap_or(&no_params);
+    if !params.is_object() {
+        let error = RpcError::new(INVALID_PARAMS, "params must be an object");
+        return (id, Err(error));
+    }
+
+    (id, query(method, params))
+}
+
+/// # Returns
+/// The JSON-RPC response carrying the request's id and its result or error.
+pub fn response(id: Value, response: Result<&QueryResult, &RpcError>) -> Value {
+    match response {
+        Ok(r) => json!({ "jsonrpc": "2.0", "id": id, "result": result(r) }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    }
+}
+
+/// Maps a method and its params onto a query.
+pub(crate) fn query(method: &str, params: &Value) -> Result<Query, RpcError> {
+    match method {
+        "add" => Ok(Query::Add(
+            Description::new(&string(params, "description")?),
+            tags(params, "tags")?,
+        )),
+        "done" => Ok(Query::Done(Index::new(number(params, "index")?))),
+        "search" => Ok(Query::Search(search_params(params)?)),
+        "count" => Ok(Query::Count(search_params(params)?)),
+        "tags" => Ok(Query::Tags),
+        "tag_stats" => Ok(Query::TagStats(stats_params(params)?)),
+        "word_stats" => Ok(Query::WordStats(stats_params(params)?)),
+        "rename_tag" => Ok(Query::RenameTag(tag(params, "from")?, tag(params, "to")?)),
+        "merge_tags" => Ok(Query::MergeTags(tag(params, "from")?, tag(params, "into")?)),
+        "drop_tag" => Ok(Query::DropTag(tag(params, "tag")?)),
+        "add_rule" => Ok(Query::AddRule(rule(params)?)),
+        "rules" => Ok(Query::Rules),
+        "suggest" => Ok(Query::Suggest(string(params, "prefix")?)),
+        "begin" => Ok(Query::Begin),
+        "commit" => Ok(Query::Commit),
+        "rollback" => Ok(Query::Rollback),
+        "done_all" => Ok(Query::DoneAll(selection(params)?)),
+        "tag_all" => Ok(Query::TagAll(tags(params, "tags")?, selection(params)?)),
+        "untag_all" => Ok(Query::UntagAll(tags(params, "tags")?, selection(params)?)),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method {}", method),
+        )),
+    }
+}
+
+/// Reads search params such as
+/// `{"words": ["milk"], "tags": ["groceries"], "phrases": [{"text": "buy milk", "slop": 1}]}`,
+/// where every field is optional.
+fn search_params(params: &Value) -> Result<SearchParams, RpcError> {
+    let words = strings(params, "words")?
+        .iter()
+        .map(|word| SearchWord::new(word))
+        .collect();
+
+    let mut sp = SearchParams::new(words, tags(params, "tags")?);
+    for phrase in array(params, "phrases")? {
+        let text = string(phrase, "text")?;
+        let slop = match phrase.get("slop") {
+            Some(_) => number(phrase, "slop")? as usize,
+            None => 0,
+        };
+
+        sp.phrases.push(SearchPhrase::new(&text, slop));
+    }
+
+    for pattern in strings(params, "regexes")? {
+        let regex = SearchRegex::new(&pattern).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+        sp.regexes.push(regex);
+    }
+
+    for pattern in strings(params, "tag_patterns")? {
+        sp.tag_patterns
+            .push(TagPattern::new(pattern.trim_start_matches('#')));
+    }
+
+    sp.facets = flag(params, "facets")?;
+    sp.group_by_tag = flag(params, "group_by_tag")?;
+    sp.explain = flag(params, "explain")?;
+    sp.highlight = flag(params, "highlight")?;
+    Ok(sp)
+}
+
+/// Reads the items of a bulk change, either `{"indices": [3, 7, 12]}`
+/// or `{"where": {"tags": ["groceries"]}}` with search params.
+fn selection(params: &Value) -> Result<Selection, RpcError> {
+    match params.get("where") {
+        Some(search) if search.is_object() => Ok(Selection::Where(search_params(search)?)),
+        Some(_) => Err(invalid("where", "an object")),
+        None => {
+            let indices = array(params, "indices")?
+                .iter()
+                .map(|index| {
+                    index
+                        .as_u64()
+                        .map(|i| (Index::new(i), Index::new(i)))
+                        .ok_or_else(|| invalid("indices", "an array of non-negative integers"))
+                })
+                .collect::<Result<_, _>>()?;
+
+            Ok(Selection::Ranges(indices))
+        }
+    }
+}
+
+/// Reads stats params such as `{"order": "name", "prefix": "gro", "limit": 10}`,
+/// where every field is optional.
+fn stats_params(params: &Value) -> Result<StatsParams, RpcError> {
+    let mut stats = StatsParams::new();
+    match params.get("order").map(|order| order.as_str()) {
+        None | Some(Some("count")) => {}
+        Some(Some("name")) => stats.order = StatsOrder::Name,
+        Some(_) => return Err(invalid("order", "\"count\" or \"name\"")),
+    }
+
+    if params.get("prefix").is_some() {
+        stats.prefix = Some(string(params, "prefix")?);
+    }
+
+    if params.get("limit").is_some() {
+        stats.limit = Some(number(params, "limit")? as usize);
+    }
+
+    Ok(stats)
+}
+
+/// Reads a rule, either `{"tag": "a", "implies": "b"}`
+/// or `{"matches": "regex", "tag": "t"}`.
+fn rule(params: &Value) -> Result<Rule, RpcError> {
+    if params.get("implies").is_some() {
+        Ok(Rule::Implies(tag(params, "tag")?, tag(params, "implies")?))
+    } else {
+        let regex = SearchRegex::new(&string(params, "matches")?)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+
+        Ok(Rule::Matches(regex, tag(params, "tag")?))
+    }
+}
+
+fn invalid(key: &str, expected: &str) -> RpcError {
+    RpcError::new(INVALID_PARAMS, format!("{} must be {}", key, expected))
+}
+
+fn string(params: &Value, key: &str) -> Result<String, RpcError> {
+    params
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| invalid(key, "a string"))
+}
+
+fn number(params: &Value, key: &str) -> Result<u64, RpcError> {
+    params
+        .get(key)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid(key, "a non-negative integer"))
+}
+
+fn flag(params: &Value, key: &str) -> Result<bool, RpcError> {
+    match params.get(key) {
+        None => Ok(false),
+        Some(value) => value.as_bool().ok_or_else(|| invalid(key, "a boolean")),
+    }
+}
+
+/// # Returns
+/// Elements of the optional array.
+fn array<'a>(params: &'a Value, key: &str) -> Result<&'a [Value], RpcError> {
+    match params.get(key) {
+        None => Ok(&[]),
+        Some(value) => value
+            .as_array()
+            .map(Vec::as_slice)
+            .ok_or_else(|| invalid(key, "an array")),
+    }
+}
+
+/// # Returns
+/// Strings of the optional array.
+fn strings(params: &Value, key: &str) -> Result<Vec<String>, RpcError> {
+    array(params, key)?
+        .iter()
+        .map(|value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid(key, "an array of strings"))
+        })
+        .collect()
+}
+
+fn tag(params: &Value, key: &str) -> Result<Tag, RpcError> {
+    valid_tag(&string(params, key)?, key)
+}
+
+/// # Returns
+/// Tags of the optional array, with or without their `#`.
+fn tags(params: &Value, key: &str) -> Result<Vec<Tag>, RpcError> {
+    strings(params, key)?
+        .iter()
+        .map(|tag| valid_tag(tag, key))
+        .collect()
+}
+
+/// # Returns
+/// The tag, with or without its `#`, if the line parser would accept it.
+fn valid_tag(tag: &str, key: &str) -> Result<Tag, RpcError> {
+    let path = tag.strip_prefix('#').unwrap_or(tag);
+    if parser::is_tag_path(path) {
+        Ok(Tag::new(path))
+    } else {
+        Err(invalid(key, "made of lowercase words separated by /"))
+    }
+}
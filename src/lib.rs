@@ -1,7 +1,20 @@
+pub mod analyzer;
+pub mod http;
+pub mod json;
 pub mod parser;
+mod phrase;
 pub mod query;
+pub mod rule;
 pub mod runner;
+pub mod server;
+mod shared;
 pub mod todo_list;
+pub mod tokenizer;
 
+pub use analyzer::*;
+use phrase::Phrase;
 pub use query::*;
+pub use rule::*;
+pub use shared::*;
 pub use todo_list::*;
+pub use tokenizer::*;
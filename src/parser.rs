@@ -3,14 +3,39 @@ use crate::*;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while, take_while1},
-    character::complete::{digit1, one_of, space0},
-    multi::{many1, separated_list},
+    character::complete::{digit1, one_of, space0, space1},
+    combinator::{all_consuming, map_res, opt, recognize},
+    error::ErrorKind,
+    multi::{many0, separated_list, separated_nonempty_list},
     sequence::{delimited, pair, preceded},
     IResult,
 };
 
 pub fn query(input: &str) -> IResult<&str, Query> {
-    alt((add, done, search))(input)
+    query_with(input, &UnicodeTokenizer::new())
+}
+
+/// Parses a query, splitting search words with the given tokenizer.
+pub fn query_with<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Query> {
+    alt((
+        add,
+        |i| done_all(i, tokenizer),
+        done,
+        |i| search(i, tokenizer),
+        |i| count(i, tokenizer),
+        list_tags,
+        tag_stats,
+        word_stats,
+        rename_tag,
+        merge_tags,
+        drop_tag,
+        add_rule,
+        list_rules,
+        suggest,
+        transaction,
+        |i| tag_all(i, tokenizer),
+        |i| untag_all(i, tokenizer),
+    ))(input)
 }
 
 fn ws(input: &str) -> IResult<&str, char> {
@@ -51,17 +76,39 @@ fn raw_word(input: &str) -> IResult<&str, String> {
     }
 }
 
+/// Parses a tag path, whose levels are separated by `/`.
+fn tag_path(input: &str) -> IResult<&str, &str> {
+    recognize(separated_nonempty_list(tag("/"), word))(input)
+}
+
+/// # Returns
+/// Whether the whole text is a tag path, as written after the `#` of a tag.
+pub(crate) fn is_tag_path(text: &str) -> bool {
+    all_consuming(tag_path)(text).is_ok()
+}
+
 fn todo_tag(input: &str) -> IResult<&str, &str> {
-    preceded(tag("#"), word)(input)
+    preceded(tag("#"), tag_path)(input)
 }
 
 fn raw_tag(input: &str) -> IResult<&str, String> {
-    match pair(tag("#"), word)(input) {
+    match pair(tag("#"), tag_path)(input) {
         Err(e) => Err(e),
         Ok((rest, (h, w))) => Ok((rest, format!("{}{}", h, w))),
     }
 }
 
+fn is_lowecase_or_dash_or_slash_or_wildcard(c: char) -> bool {
+    is_lowecase_or_dash(c) || c == '/' || c == '*' || c == '?'
+}
+
+fn raw_tag_pattern(input: &str) -> IResult<&str, &str> {
+    preceded(
+        tag("#"),
+        take_while1(is_lowecase_or_dash_or_slash_or_wildcard),
+    )(input)
+}
+
 fn description(input: &str) -> IResult<&str, String> {
     match delimited(tag("\""), sentence, tag("\""))(input) {
         Err(e) => Err(e),
@@ -70,43 +117,143 @@ fn description(input: &str) -> IResult<&str, String> {
 }
 
 fn tags(input: &str) -> IResult<&str, Vec<Tag>> {
-    match separated_list(ws, todo_tag)(input) {
+    match separated_list(space1, todo_tag)(input) {
         Err(e) => Err(e),
         Ok((rest, ts)) => Ok((rest, ts.iter().map(|w| Tag::new(w)).collect())),
     }
 }
 
 fn done(input: &str) -> IResult<&str, Query> {
-    match preceded(pair(tag("done"), ws), many1(digit1))(input) {
+    match preceded(pair(tag("done"), ws), map_res(digit1, str::parse::<u64>))(input) {
         Err(e) => Err(e),
-        Ok((rest, i)) => Ok((rest, Query::Done(Index::new(vec_to_u64(i))))),
+        Ok((rest, i)) => Ok((rest, Query::Done(Index::new(i)))),
     }
 }
 
-fn vec_to_u64(dss: Vec<&str>) -> u64 {
-    let ds = dss
-        .iter()
-        .fold("".to_string(), |acc, x| format!("{}{}", acc, x));
-    ds.parse::<u64>().unwrap()
+/// Parses the items of `done 3 7 12`, `done 10..20` or `done where #groceries`,
+/// leaving a single index to `done`, and failing without trying `done`
+/// on anything else that looks like a bulk change.
+fn done_all<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Query> {
+    let (args, _) = pair(tag("done"), space1)(input)?;
+    let bulk =
+        args.starts_with("where") || args.contains("..") || args.split_whitespace().count() > 1;
+
+    if !bulk {
+        return Err(nom::Err::Error((input, ErrorKind::Verify)));
+    }
+
+    match all_consuming(|i| selection(i, tokenizer))(args) {
+        Err(_) => Err(nom::Err::Failure((input, ErrorKind::Verify))),
+        Ok((rest, s)) => Ok((rest, Query::DoneAll(s))),
+    }
+}
+
+fn tag_all<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Query> {
+    match all_consuming(preceded(pair(tag("tag"), space1), |i| {
+        tags_and_selection(i, tokenizer)
+    }))(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (ts, s))) => Ok((rest, Query::TagAll(ts, s))),
+    }
+}
+
+fn untag_all<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Query> {
+    match all_consuming(preceded(pair(tag("untag"), space1), |i| {
+        tags_and_selection(i, tokenizer)
+    }))(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (ts, s))) => Ok((rest, Query::UntagAll(ts, s))),
+    }
+}
+
+fn tags_and_selection<'a>(
+    input: &'a str,
+    tokenizer: &dyn Tokenizer,
+) -> IResult<&'a str, (Vec<Tag>, Selection)> {
+    match pair(
+        separated_nonempty_list(space1, todo_tag),
+        preceded(space1, |i| selection(i, tokenizer)),
+    )(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (ts, s))) => Ok((rest, (ts.iter().map(|t| Tag::new(t)).collect(), s))),
+    }
+}
+
+/// Parses either `where` followed by a search, or indices and ranges such as `3 10..20`.
+fn selection<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Selection> {
+    let search = preceded(
+        pair(tag("where"), space1),
+        separated_list(space1, search_word_or_tag),
+    );
+
+    match search(input) {
+        Ok((rest, mash)) => Ok((rest, Selection::Where(mash_to_params(mash, tokenizer)))),
+        Err(_) => match delimited(space0, separated_nonempty_list(space1, range), space0)(input) {
+            Err(e) => Err(e),
+            Ok((rest, ranges)) => Ok((rest, Selection::Ranges(ranges))),
+        },
+    }
+}
+
+/// Parses an index, or an inclusive range of indices such as `10..20`.
+fn range(input: &str) -> IResult<&str, (Index, Index)> {
+    let index = |i| map_res(digit1, str::parse::<u64>)(i);
+    match pair(index, opt(preceded(tag(".."), index)))(input) {
+        Err(e) => Err(e),
+        Ok((rest, (start, end))) => {
+            Ok((rest, (Index::new(start), Index::new(end.unwrap_or(start)))))
+        }
+    }
 }
 
 enum SearchWordOrTag {
     RawWord(String),
     RawTag(String),
+    Phrase(String, usize),
+    Regex(SearchRegex),
+    TagPattern(TagPattern),
+}
+
+fn search<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Query> {
+    match preceded(
+        pair(tag("search"), space1),
+        separated_list(space1, search_word_or_tag),
+    )(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, mash)) => Ok((rest, Query::Search(mash_to_params(mash, tokenizer)))),
+    }
 }
 
-fn search(input: &str) -> IResult<&str, Query> {
+fn count<'a>(input: &'a str, tokenizer: &dyn Tokenizer) -> IResult<&'a str, Query> {
     match preceded(
-        pair(tag("search"), ws),
-        separated_list(tag(" "), search_word_or_tag),
+        pair(tag("count"), space1),
+        separated_list(space1, search_word_or_tag),
     )(input)
     {
         Err(e) => Err(e),
-        Ok((rest, mash)) => Ok((rest, mash_to_query(mash))),
+        Ok((rest, mash)) => Ok((rest, Query::Count(mash_to_params(mash, tokenizer)))),
     }
 }
 
 fn search_word_or_tag(input: &str) -> IResult<&str, SearchWordOrTag> {
+    if let Ok((rest, (p, slop))) = phrase(input) {
+        return Ok((rest, SearchWordOrTag::Phrase(p, slop)));
+    }
+
+    if let Ok((rest, r)) = regex(input) {
+        return Ok((rest, SearchWordOrTag::Regex(r)));
+    }
+
+    if let Ok((rest, p)) = raw_tag_pattern(input) {
+        if TagPattern::is_pattern(p) {
+            return Ok((rest, SearchWordOrTag::TagPattern(TagPattern::new(p))));
+        }
+    }
+
     match alt((raw_tag, raw_word))(input) {
         Err(e) => Err(e),
         Ok((rest, wot)) => {
@@ -119,17 +266,295 @@ fn search_word_or_tag(input: &str) -> IResult<&str, SearchWordOrTag> {
     }
 }
 
-fn mash_to_query(mash: Vec<SearchWordOrTag>) -> Query {
+/// Parses a quoted phrase, optionally followed by a `~N` proximity.
+fn phrase(input: &str) -> IResult<&str, (String, usize)> {
+    let slop = map_res(digit1, str::parse::<usize>);
+    match pair(description, opt(preceded(tag("~"), slop)))(input) {
+        Err(e) => Err(e),
+        Ok((rest, (p, slop))) => Ok((rest, (p, slop.unwrap_or(0)))),
+    }
+}
+
+/// Parses a `/`-delimited regular expression, in which `\/` stands for a slash.
+fn regex(input: &str) -> IResult<&str, SearchRegex> {
+    let (rest, _) = tag("/")(input)?;
+    let mut pattern = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' => {
+                return match SearchRegex::new(&pattern) {
+                    Ok(r) => Ok((&rest[i + 1..], r)),
+                    Err(_) => Err(nom::Err::Error((input, ErrorKind::MapRes))),
+                };
+            }
+            '\\' => match chars.next() {
+                Some((_, '/')) => pattern.push('/'),
+                Some((_, c)) => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+                None => break,
+            },
+            c => pattern.push(c),
+        }
+    }
+
+    Err(nom::Err::Error((input, ErrorKind::Tag)))
+}
+
+/// Removes the modifier words from the end of the search, if present.
+///
+/// # Returns
+/// Whether the modifier was present.
+fn take_modifier(mash: &mut Vec<SearchWordOrTag>, modifier: &[&str]) -> bool {
+    if mash.len() < modifier.len() {
+        return false;
+    }
+
+    let start = mash.len() - modifier.len();
+    let present = mash[start..]
+        .iter()
+        .zip(modifier)
+        .all(|(wot, m)| matches!(wot, SearchWordOrTag::RawWord(w) if w == m));
+
+    if present {
+        mash.truncate(start);
+    }
+
+    present
+}
+
+fn mash_to_params(mut mash: Vec<SearchWordOrTag>, tokenizer: &dyn Tokenizer) -> SearchParams {
+    let mut facets = false;
+    let mut group_by_tag = false;
+    let mut explain = false;
+    let mut highlight = false;
+    loop {
+        if take_modifier(&mut mash, &["facets"]) {
+            facets = true;
+        } else if take_modifier(&mut mash, &["group", "by", "tag"]) {
+            group_by_tag = true;
+        } else if take_modifier(&mut mash, &["explain"]) {
+            explain = true;
+        } else if take_modifier(&mut mash, &["highlight"]) {
+            highlight = true;
+        } else {
+            break;
+        }
+    }
+
     let mut search_words: Vec<SearchWord> = vec![];
     let mut tags: Vec<Tag> = vec![];
+    let mut phrases: Vec<SearchPhrase> = vec![];
+    let mut regexes: Vec<SearchRegex> = vec![];
+    let mut tag_patterns: Vec<TagPattern> = vec![];
     for i in mash {
         match i {
-            SearchWordOrTag::RawWord(w) => search_words.push(SearchWord::new(&w)),
+            SearchWordOrTag::RawWord(w) => search_words.extend(
+                tokenizer
+                    .tokenize_query(&w)
+                    .into_iter()
+                    .map(SearchWord::new),
+            ),
             SearchWordOrTag::RawTag(t) => tags.push(Tag::new(&t)),
+            SearchWordOrTag::Phrase(p, slop) => phrases.push(SearchPhrase::new(&p, slop)),
+            SearchWordOrTag::Regex(r) => regexes.push(r),
+            SearchWordOrTag::TagPattern(p) => tag_patterns.push(p),
         }
     }
-    Query::Search(SearchParams {
+    SearchParams {
         words: search_words,
         tags,
-    })
+        phrases,
+        regexes,
+        tag_patterns,
+        facets,
+        group_by_tag,
+        explain,
+        highlight,
+    }
+}
+
+fn list_tags(input: &str) -> IResult<&str, Query> {
+    match all_consuming(pair(tag("tags"), space0))(input) {
+        Err(e) => Err(e),
+        Ok((rest, _)) => Ok((rest, Query::Tags)),
+    }
+}
+
+fn tag_stats(input: &str) -> IResult<&str, Query> {
+    match all_consuming(preceded(
+        tag("tags"),
+        pair(stats_params(preceded(opt(tag("#")), tag_path)), space0),
+    ))(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (params, _))) => Ok((rest, Query::TagStats(params))),
+    }
+}
+
+fn word_stats(input: &str) -> IResult<&str, Query> {
+    match all_consuming(preceded(tag("words"), pair(stats_params(word), space0)))(input) {
+        Err(e) => Err(e),
+        Ok((rest, (params, _))) => Ok((rest, Query::WordStats(params))),
+    }
+}
+
+enum StatsOption {
+    Order(StatsOrder),
+    Prefix(String),
+    Limit(usize),
+}
+
+/// Parses stats options, each preceded by whitespace.
+fn stats_params<'a>(
+    prefix: impl Fn(&'a str) -> IResult<&'a str, &'a str>,
+) -> impl Fn(&'a str) -> IResult<&'a str, StatsParams> {
+    move |input: &'a str| {
+        let (rest, options) = many0(preceded(
+            space1,
+            alt((
+                stats_order,
+                |i| {
+                    preceded(pair(tag("prefix"), space1), &prefix)(i)
+                        .map(|(rest, p)| (rest, StatsOption::Prefix(p.to_string())))
+                },
+                stats_limit,
+            )),
+        ))(input)?;
+
+        let mut params = StatsParams::new();
+        for option in options {
+            match option {
+                StatsOption::Order(order) => params.order = order,
+                StatsOption::Prefix(p) => params.prefix = Some(p),
+                StatsOption::Limit(limit) => params.limit = Some(limit),
+            }
+        }
+
+        Ok((rest, params))
+    }
+}
+
+fn stats_order(input: &str) -> IResult<&str, StatsOption> {
+    match preceded(pair(tag("by"), space1), alt((tag("count"), tag("name"))))(input) {
+        Err(e) => Err(e),
+        Ok((rest, "count")) => Ok((rest, StatsOption::Order(StatsOrder::Count))),
+        Ok((rest, _)) => Ok((rest, StatsOption::Order(StatsOrder::Name))),
+    }
+}
+
+fn stats_limit(input: &str) -> IResult<&str, StatsOption> {
+    match preceded(
+        pair(tag("limit"), space1),
+        map_res(digit1, str::parse::<usize>),
+    )(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, n)) => Ok((rest, StatsOption::Limit(n))),
+    }
+}
+
+fn rename_tag(input: &str) -> IResult<&str, Query> {
+    match preceded(
+        pair(tag("rename-tag"), space1),
+        pair(todo_tag, preceded(space1, todo_tag)),
+    )(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (from, to))) => Ok((rest, Query::RenameTag(Tag::new(from), Tag::new(to)))),
+    }
+}
+
+fn merge_tags(input: &str) -> IResult<&str, Query> {
+    match preceded(
+        pair(tag("merge-tags"), space1),
+        pair(todo_tag, preceded(space1, todo_tag)),
+    )(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (from, into))) => Ok((rest, Query::MergeTags(Tag::new(from), Tag::new(into)))),
+    }
+}
+
+fn drop_tag(input: &str) -> IResult<&str, Query> {
+    match preceded(pair(tag("drop-tag"), space1), todo_tag)(input) {
+        Err(e) => Err(e),
+        Ok((rest, t)) => Ok((rest, Query::DropTag(Tag::new(t)))),
+    }
+}
+
+fn add_rule(input: &str) -> IResult<&str, Query> {
+    match preceded(pair(tag("rule"), space1), alt((implies_rule, matches_rule)))(input) {
+        Err(e) => Err(e),
+        Ok((rest, r)) => Ok((rest, Query::AddRule(r))),
+    }
+}
+
+fn implies_rule(input: &str) -> IResult<&str, Rule> {
+    match pair(
+        todo_tag,
+        preceded(delimited(space1, tag("implies"), space1), todo_tag),
+    )(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, (condition, t))) => Ok((rest, Rule::Implies(Tag::new(condition), Tag::new(t)))),
+    }
+}
+
+fn matches_rule(input: &str) -> IResult<&str, Rule> {
+    let (rest, (pattern, t)) = preceded(
+        pair(
+            tag("description"),
+            delimited(space1, tag("matches"), space1),
+        ),
+        pair(
+            delimited(tag("\""), take_while(|c| c != '"'), tag("\"")),
+            preceded(delimited(space1, tag("=>"), space1), todo_tag),
+        ),
+    )(input)?;
+
+    match SearchRegex::new(pattern) {
+        Err(_) => Err(nom::Err::Error((input, ErrorKind::MapRes))),
+        Ok(r) => Ok((rest, Rule::Matches(r, Tag::new(t)))),
+    }
+}
+
+fn list_rules(input: &str) -> IResult<&str, Query> {
+    match all_consuming(pair(tag("rules"), space0))(input) {
+        Err(e) => Err(e),
+        Ok((rest, _)) => Ok((rest, Query::Rules)),
+    }
+}
+
+fn suggest(input: &str) -> IResult<&str, Query> {
+    match all_consuming(delimited(
+        pair(tag("suggest"), space1),
+        alt((
+            recognize(preceded(
+                tag("#"),
+                take_while(|c| is_lowecase_or_dash(c) || c == '/'),
+            )),
+            word,
+        )),
+        space0,
+    ))(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, prefix)) => Ok((rest, Query::Suggest(prefix.to_string()))),
+    }
+}
+
+fn transaction(input: &str) -> IResult<&str, Query> {
+    match all_consuming(pair(
+        alt((tag("begin"), tag("commit"), tag("rollback"))),
+        space0,
+    ))(input)
+    {
+        Err(e) => Err(e),
+        Ok((rest, ("begin", _))) => Ok((rest, Query::Begin)),
+        Ok((rest, ("commit", _))) => Ok((rest, Query::Commit)),
+        Ok((rest, _)) => Ok((rest, Query::Rollback)),
+    }
 }
@@ -0,0 +1,94 @@
+use crate::*;
+
+/// A search phrase split into the terms expected at each of its positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Phrase {
+    /// Word offset within the phrase, and the alternative terms expected there.
+    groups: Vec<(usize, Vec<String>)>,
+    slop: usize,
+}
+
+impl Phrase {
+    pub fn new(analyzer: &Analyzer, phrase: &SearchPhrase) -> Phrase {
+        let mut groups: Vec<(usize, Vec<String>)> = vec![];
+        for (position, term) in analyzer.analyze_phrase(&phrase.text) {
+            match groups.last_mut() {
+                Some((offset, terms)) if *offset == position => terms.push(term),
+                _ => groups.push((position, vec![term])),
+            }
+        }
+
+        Phrase {
+            groups,
+            slop: phrase.slop,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// # Returns
+    /// Alternative terms expected at each position of the phrase.
+    pub fn terms(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.groups.iter().map(|(_, terms)| terms)
+    }
+
+    /// # Arguments
+    /// `positions`: Sorted positions at which each group of alternative terms occurs.
+    ///
+    /// # Returns
+    /// Whether the terms occur in order, spread over at most `slop` more words than the phrase.
+    pub fn matches_positions(&self, positions: &[&[usize]]) -> bool {
+        self.find_positions(positions).is_some()
+    }
+
+    /// # Arguments
+    /// `positions`: Sorted positions at which each group of alternative terms occurs.
+    ///
+    /// # Returns
+    /// First and last position of the first occurrence of the phrase.
+    pub fn find_positions(&self, positions: &[&[usize]]) -> Option<(usize, usize)> {
+        let (first, rest) = positions.split_first()?;
+        let span = self.groups.last().unwrap().0 - self.groups[0].0;
+        first.iter().find_map(|start| {
+            let mut end = *start;
+            for group in rest {
+                end = *group.iter().find(|position| **position > end)?;
+            }
+
+            if end - start <= span.saturating_add(self.slop) {
+                Some((*start, end))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// # Returns
+    /// Whether the analyzed text contains the phrase.
+    pub fn matches(&self, terms: &[(usize, String)]) -> bool {
+        self.find(terms).is_some()
+    }
+
+    /// # Returns
+    /// First and last position of the first occurrence of the phrase in the analyzed text.
+    pub fn find(&self, terms: &[(usize, String)]) -> Option<(usize, usize)> {
+        let positions = self
+            .terms()
+            .map(|alternatives| {
+                let mut positions = terms
+                    .iter()
+                    .filter(|(_, term)| alternatives.contains(term))
+                    .map(|(position, _)| *position)
+                    .collect::<Vec<_>>();
+
+                positions.sort_unstable();
+                positions
+            })
+            .collect::<Vec<_>>();
+
+        let positions = positions.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        self.find_positions(&positions)
+    }
+}
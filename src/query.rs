@@ -1,21 +1,112 @@
+use std::convert::TryFrom;
 use std::fmt;
 
 use crate::*;
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Query {
     Add(Description, Vec<Tag>),
     Done(Index),
     Search(SearchParams),
+    Count(SearchParams),
+    Tags,
+    TagStats(StatsParams),
+    WordStats(StatsParams),
+    RenameTag(Tag, Tag),
+    MergeTags(Tag, Tag),
+    DropTag(Tag),
+    AddRule(Rule),
+    Rules,
+    Suggest(String),
+    Begin,
+    Commit,
+    Rollback,
+    DoneAll(Selection),
+    TagAll(Vec<Tag>, Selection),
+    UntagAll(Vec<Tag>, Selection),
+}
+
+impl Query {
+    /// # Returns
+    /// Whether running the query leaves the list unchanged.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Query::Add(..)
+            | Query::Done(_)
+            | Query::RenameTag(..)
+            | Query::MergeTags(..)
+            | Query::DropTag(_)
+            | Query::AddRule(_)
+            | Query::Commit
+            | Query::DoneAll(_)
+            | Query::TagAll(..)
+            | Query::UntagAll(..) => false,
+            Query::Search(_)
+            | Query::Count(_)
+            | Query::Tags
+            | Query::TagStats(_)
+            | Query::WordStats(_)
+            | Query::Rules
+            | Query::Suggest(_)
+            | Query::Begin
+            | Query::Rollback => true,
+        }
+    }
+}
+
+/// Items a bulk change applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Selection {
+    /// Inclusive ranges of indices, a single index being a range of one.
+    Ranges(Vec<(Index, Index)>),
+
+    /// Active items the search finds.
+    Where(SearchParams),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SearchParams {
     pub words: Vec<SearchWord>,
     pub tags: Vec<Tag>,
+    pub phrases: Vec<SearchPhrase>,
+    pub regexes: Vec<SearchRegex>,
+    pub tag_patterns: Vec<TagPattern>,
+
+    /// Whether to count the tags of the matching items.
+    pub facets: bool,
+
+    /// Whether to only count the matching items carrying each tag.
+    pub group_by_tag: bool,
+
+    /// Whether to explain why each item matched.
+    pub explain: bool,
+
+    /// Whether to highlight the characters the search words matched.
+    pub highlight: bool,
+}
+
+impl SearchParams {
+    pub fn new(words: Vec<SearchWord>, tags: Vec<Tag>) -> SearchParams {
+        SearchParams {
+            words,
+            tags,
+            phrases: vec![],
+            regexes: vec![],
+            tag_patterns: vec![],
+            facets: false,
+            group_by_tag: false,
+            explain: false,
+            highlight: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SearchWord(pub String);
 impl SearchWord {
     pub fn new(s: &str) -> SearchWord {
@@ -23,11 +114,310 @@ impl SearchWord {
     }
 }
 
+/// Words that must appear in order, with at most `slop` extra words between them.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchPhrase {
+    pub text: String,
+    pub slop: usize,
+}
+
+impl SearchPhrase {
+    pub fn new(text: &str, slop: usize) -> SearchPhrase {
+        SearchPhrase {
+            text: text.to_owned(),
+            slop,
+        }
+    }
+}
+
+impl fmt::Display for SearchPhrase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.slop == 0 {
+            write!(f, r#""{}""#, self.text)
+        } else {
+            write!(f, r#""{}"~{}"#, self.text, self.slop)
+        }
+    }
+}
+
+/// Regular expression matched against item descriptions.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub struct SearchRegex {
+    regex: regex::Regex,
+
+    /// Literals every match starts with, if they are known.
+    prefixes: Option<Vec<String>>,
+}
+
+impl SearchRegex {
+    pub fn new(pattern: &str) -> Result<SearchRegex, regex::Error> {
+        let regex = regex::Regex::new(pattern)?;
+        let prefixes = regex_syntax::parse(pattern).ok().and_then(|hir| {
+            let prefixes = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+            prefixes.literals().map(|literals| {
+                literals
+                    .iter()
+                    .map(|literal| String::from_utf8_lossy(literal.as_bytes()).into_owned())
+                    .collect()
+            })
+        });
+
+        Ok(SearchRegex { regex, prefixes })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.regex.is_match(haystack)
+    }
+
+    /// # Returns
+    /// Text of the first match in the haystack.
+    pub fn find<'a>(&self, haystack: &'a str) -> Option<&'a str> {
+        self.regex.find(haystack).map(|m| m.as_str())
+    }
+
+    /// # Returns
+    /// Literals every match starts with, or `None` if any text may start a match.
+    pub fn prefixes(&self) -> Option<&[String]> {
+        self.prefixes.as_deref()
+    }
+}
+
+impl PartialEq for SearchRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SearchRegex {}
+
+impl TryFrom<String> for SearchRegex {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        SearchRegex::new(&pattern)
+    }
+}
+
+impl From<SearchRegex> for String {
+    fn from(regex: SearchRegex) -> Self {
+        regex.as_str().to_string()
+    }
+}
+
+impl fmt::Display for SearchRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "/{}/", self.as_str().replace('/', "\\/"))
+    }
+}
+
+/// Glob pattern matched against tags, where `*` stands for any characters
+/// and `?` for a single one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TagPattern(String);
+
+impl TagPattern {
+    pub fn new(s: &str) -> TagPattern {
+        TagPattern(s.to_owned())
+    }
+
+    pub fn value(&self) -> &str {
+        &self.0
+    }
+
+    /// # Returns
+    /// Whether the string contains wildcards.
+    pub fn is_pattern(s: &str) -> bool {
+        s.contains(['*', '?'])
+    }
+
+    /// # Returns
+    /// Characters every matching tag starts with.
+    pub fn prefix(&self) -> &str {
+        let end = self.0.find(['*', '?']).unwrap_or(self.0.len());
+        &self.0[..end]
+    }
+
+    pub fn matches(&self, tag: &Tag) -> bool {
+        let pattern = self.0.chars().collect::<Vec<_>>();
+        let value = tag.value().chars().collect::<Vec<_>>();
+
+        // Position after the last `*`, and the value position it was matched up to.
+        let mut star: Option<(usize, usize)> = None;
+        let (mut p, mut v) = (0, 0);
+        while v < value.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    star = Some((p + 1, v));
+                    p += 1;
+                }
+                Some(c) if *c == '?' || *c == value[v] => {
+                    p += 1;
+                    v += 1;
+                }
+                _ => match star {
+                    Some((star_p, star_v)) => {
+                        star = Some((star_p, star_v + 1));
+                        p = star_p;
+                        v = star_v + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        pattern[p..].iter().all(|c| *c == '*')
+    }
+}
+
+impl fmt::Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Options for listing how many active items carry each tag or word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsParams {
+    pub order: StatsOrder,
+
+    /// Only list tags or words starting with this.
+    pub prefix: Option<String>,
+
+    /// Maximum number of entries to list.
+    pub limit: Option<usize>,
+}
+
+impl StatsParams {
+    pub fn new() -> StatsParams {
+        StatsParams {
+            order: StatsOrder::Count,
+            prefix: None,
+            limit: None,
+        }
+    }
+}
+
+impl Default for StatsParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatsOrder {
+    /// Most frequent first.
+    Count,
+
+    /// Alphabetical.
+    Name,
+}
+
+/// Why an item matched a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Explanation {
+    pub index: Index,
+    pub hits: Vec<Hit>,
+}
+
+/// A search word, tag, phrase or pattern, and the part of the item it matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hit {
+    pub term: String,
+    pub matched: String,
+}
+
+impl Hit {
+    pub fn new(term: impl ToString, matched: impl ToString) -> Hit {
+        Hit {
+            term: term.to_string(),
+            matched: matched.to_string(),
+        }
+    }
+}
+
+/// Characters of an item's description matched by the search words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Highlight {
+    pub index: Index,
+
+    /// Sorted, disjoint byte ranges of the description.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Highlight {
+    /// Surrounds the highlighted characters of the description with brackets,
+    /// or with ANSI colour codes if `ansi` is set.
+    pub fn mark(&self, description: &str, ansi: bool) -> String {
+        let (open, close) = if ansi {
+            ("\x1b[1;31m", "\x1b[0m")
+        } else {
+            ("[", "]")
+        };
+
+        let mut marked = String::new();
+        let mut last = 0;
+        for (start, end) in self.ranges.iter() {
+            marked.push_str(&description[last..*start]);
+            marked.push_str(open);
+            marked.push_str(&description[*start..*end]);
+            marked.push_str(close);
+            last = *end;
+        }
+
+        marked.push_str(&description[last..]);
+        marked
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum QueryResult {
     Added(TodoItem),
     Done,
-    Found(Vec<todo_list::TodoItem>),
+    Found {
+        items: Vec<todo_list::TodoItem>,
+
+        /// Tags of the items, with the number of items carrying them.
+        facets: Option<Vec<(Tag, usize)>>,
+
+        /// Why each item matched.
+        explanations: Option<Vec<Explanation>>,
+
+        /// Characters to highlight, with brackets, or with ANSI colour codes
+        /// when formatted with `{:#}`.
+        highlights: Option<Vec<Highlight>>,
+
+        /// Words and tags close to the searched ones, when nothing was found.
+        suggestions: Vec<String>,
+    },
+    Count(usize),
+    Groups(Vec<(Tag, usize)>),
+    Tags(Vec<TagNode>),
+    Counts(Vec<(String, usize)>),
+    Updated(usize),
+    Rules(Vec<Rule>),
+
+    /// Number of changes staged by the open transaction.
+    Staged(usize),
+
+    /// Number of changes the transaction applied.
+    Committed(usize),
+
+    /// Number of changes the transaction dropped.
+    RolledBack(usize),
 }
 
 impl fmt::Display for QueryResult {
@@ -35,22 +425,118 @@ impl fmt::Display for QueryResult {
         match &self {
             QueryResult::Added(ti) => write!(f, "{}", ti.index),
             QueryResult::Done => write!(f, "done"),
-            QueryResult::Found(rs) => {
-                let mut sorted = rs.iter().collect::<Vec<_>>();
+            QueryResult::Found {
+                items,
+                facets,
+                explanations,
+                highlights,
+                suggestions,
+            } => {
+                let mut sorted = items.iter().collect::<Vec<_>>();
                 sorted.sort_by_key(|r| r.index);
 
                 let mut buff: Vec<String> = vec![];
                 buff.push(format!("{} item(s) found", sorted.len()));
                 for i in sorted.iter().rev() {
-                    buff.push(format!("{}", i));
+                    let highlight = highlights
+                        .iter()
+                        .flatten()
+                        .find(|highlight| highlight.index == i.index);
+
+                    match highlight {
+                        Some(highlight) => {
+                            let description = highlight.mark(i.description.value(), f.alternate());
+                            let tags = i.tags.iter().map(Tag::to_string).collect::<Vec<_>>();
+                            buff.push(format!(
+                                r#"{} "{}" {}"#,
+                                i.index,
+                                description,
+                                tags.join(" ")
+                            ));
+                        }
+                        None => buff.push(format!("{}", i)),
+                    }
+
+                    let explanation = explanations
+                        .iter()
+                        .flatten()
+                        .find(|explanation| explanation.index == i.index);
+
+                    if let Some(explanation) = explanation {
+                        for hit in explanation.hits.iter() {
+                            buff.push(format!("  {} matched {}", hit.term, hit.matched));
+                        }
+                    }
+                }
+
+                if let Some(facets) = facets {
+                    let facets = facets
+                        .iter()
+                        .map(|(tag, count)| format!("{} ({})", tag, count))
+                        .collect::<Vec<_>>();
+
+                    buff.push(format!("facets: {}", facets.join(", ")));
+                }
+
+                if !suggestions.is_empty() {
+                    buff.push(format!("did you mean: {}", suggestions.join(", ")));
                 }
                 write!(f, "{}", buff.join("\n"))
             }
+            QueryResult::Count(n) => write!(f, "{} item(s) found", n),
+            QueryResult::Groups(groups) => {
+                let mut buff: Vec<String> = vec![];
+                buff.push(format!("{} group(s) found", groups.len()));
+                for (tag, count) in groups.iter() {
+                    buff.push(format!("{} ({})", tag, count));
+                }
+                write!(f, "{}", buff.join("\n"))
+            }
+            QueryResult::Updated(n) => write!(f, "{} item(s) updated", n),
+            QueryResult::Staged(n) => write!(f, "{} change(s) staged", n),
+            QueryResult::Committed(n) => write!(f, "{} change(s) committed", n),
+            QueryResult::RolledBack(n) => write!(f, "{} change(s) rolled back", n),
+            QueryResult::Counts(counts) => {
+                let mut buff: Vec<String> = vec![];
+                buff.push(format!("{} term(s) found", counts.len()));
+                for (term, count) in counts.iter() {
+                    buff.push(format!("{} ({})", term, count));
+                }
+                write!(f, "{}", buff.join("\n"))
+            }
+            QueryResult::Rules(rules) => {
+                let mut buff: Vec<String> = vec![];
+                buff.push(format!("{} rule(s) found", rules.len()));
+                for rule in rules.iter() {
+                    buff.push(rule.to_string());
+                }
+                write!(f, "{}", buff.join("\n"))
+            }
+            QueryResult::Tags(nodes) => {
+                let mut buff: Vec<String> = vec![];
+                push_tag_nodes(&mut buff, nodes, 0);
+                buff.insert(0, format!("{} tag(s) found", buff.len()));
+                write!(f, "{}", buff.join("\n"))
+            }
         }
     }
 }
 
+/// Pushes a line for each node, indented by its depth.
+fn push_tag_nodes(buff: &mut Vec<String>, nodes: &[TagNode], depth: usize) {
+    for node in nodes {
+        buff.push(format!(
+            "{}{} ({})",
+            "  ".repeat(depth),
+            node.tag,
+            node.count
+        ));
+        push_tag_nodes(buff, &node.children, depth + 1);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QueryError(pub String);
 
 impl fmt::Display for QueryError {
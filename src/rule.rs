@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crate::*;
+
+/// Tags items automatically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rule {
+    /// Items with the first tag, or one of its descendants, also get the second tag.
+    Implies(Tag, Tag),
+
+    /// Items whose description matches the regular expression get the tag.
+    Matches(SearchRegex, Tag),
+}
+
+impl Rule {
+    /// Adds the rule's tag if the item satisfies the rule.
+    ///
+    /// # Returns
+    /// Whether a tag was added.
+    pub fn apply(&self, description: &Description, tags: &mut Vec<Tag>) -> bool {
+        let (applies, tag) = match self {
+            Rule::Implies(condition, tag) => (tags.iter().any(|t| condition.includes(t)), tag),
+            Rule::Matches(regex, tag) => (regex.is_match(description.value()), tag),
+        };
+
+        if applies && !tags.contains(tag) {
+            tags.push(tag.clone());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Implies(condition, tag) => write!(f, "{} implies {}", condition, tag),
+            Rule::Matches(regex, tag) => {
+                write!(f, r#"description matches "{}" => {}"#, regex.as_str(), tag)
+            }
+        }
+    }
+}
+
+/// Applies the rules until no more tags are added, so that implied tags can imply others.
+///
+/// # Returns
+/// Whether a tag was added.
+pub fn apply_rules(rules: &[Rule], description: &Description, tags: &mut Vec<Tag>) -> bool {
+    let mut changed = false;
+    loop {
+        let mut added = false;
+        for rule in rules {
+            added |= rule.apply(description, tags);
+        }
+
+        if !added {
+            return changed;
+        }
+
+        changed = true;
+    }
+}
@@ -1,20 +1,271 @@
 use crate::*;
+use serde_json::Value;
 use std::io;
 
+/// Number of completions listed by `suggest`.
+const SUGGESTIONS: usize = 10;
+
+/// How results are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Whether to highlight matches with ANSI colour codes rather than brackets.
+    pub color: bool,
+
+    pub format: Format,
+}
+
+/// Output format of the results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable lines.
+    #[default]
+    Text,
+
+    /// One JSON object per command, errors included, written to the output.
+    Json,
+
+    /// JSON-RPC 2.0 requests in, one per line, and responses out.
+    JsonRpc,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "json-rpc" => Ok(Format::JsonRpc),
+            _ => Err(format!(
+                "unknown format {}, expected json, json-rpc or text",
+                s
+            )),
+        }
+    }
+}
+
+/// State kept between the lines of a client: the changes staged by `begin`,
+/// until `commit` applies them together or `rollback` drops them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    staged: Option<Vec<Query>>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session { staged: None }
+    }
+
+    /// # Returns
+    /// Whether a transaction is open.
+    pub fn in_transaction(&self) -> bool {
+        self.staged.is_some()
+    }
+}
+
 pub fn run_line(line: &str, tl: &mut TodoList, out: &mut impl io::Write, err: &mut impl io::Write) {
-    if let Ok((_, q)) = parser::query(line) {
-        match run_query(q, tl) {
-            Ok(r) => {
-                writeln!(out, "{}", r).expect("could not write to out");
-            }
-            Err(e) => {
-                writeln!(err, "Error: {}", e).expect("could not write to err");
-            }
+    run_line_with(line, tl, out, err, Options::default())
+}
+
+/// Runs the line on its own, so transactions are refused.
+pub fn run_line_with(
+    line: &str,
+    tl: &mut TodoList,
+    out: &mut impl io::Write,
+    err: &mut impl io::Write,
+    options: Options,
+) {
+    if let Some(request) = parse_request(line, tl.tokenizer(), options.format) {
+        let response = run_request(request, |q| run_query(q, tl));
+        write_response(response, out, err, options);
+    }
+}
+
+/// Runs the line as the next one of the session,
+/// staging the changes while a transaction is open.
+pub fn run_session_line(
+    line: &str,
+    tl: &mut TodoList,
+    session: &mut Session,
+    out: &mut impl io::Write,
+    err: &mut impl io::Write,
+    options: Options,
+) {
+    if let Some(request) = parse_request(line, tl.tokenizer(), options.format) {
+        let response = run_request(request, |q| match step(q, session) {
+            Step::Run(q) => run_query(q, tl),
+            Step::Commit(staged) => commit(staged, tl),
+            Step::Answer(result) => result,
+        });
+        write_response(response, out, err, options);
+    }
+}
+
+/// Runs the line of the session against a list shared between threads.
+/// Queries reading the list run on a snapshot, so they block neither each other nor changes,
+/// and no lock is held while the response is written.
+pub fn run_shared_line(
+    line: &str,
+    tl: &SharedTodoList,
+    session: &mut Session,
+    out: &mut impl io::Write,
+    err: &mut impl io::Write,
+    options: Options,
+) {
+    let request = tl.read(|tl| parse_request(line, tl.tokenizer(), options.format));
+    if let Some(request) = request {
+        let response = run_request(request, |q| match step(q, session) {
+            Step::Run(q) => run_shared_query(q, tl),
+            Step::Commit(staged) => tl.write(|tl| commit(staged, tl)),
+            Step::Answer(result) => result,
+        });
+        write_response(response, out, err, options);
+    }
+}
+
+/// Runs the query against a list shared between threads,
+/// only taking the write lock if the query changes the list.
+/// Other queries run on a snapshot, so that changes are applied while they run.
+pub fn run_shared_query(q: Query, tl: &SharedTodoList) -> Result<QueryResult, QueryError> {
+    if q.is_read_only() {
+        run_read_query(q, &tl.snapshot())
+    } else {
+        tl.write(|tl| run_query(q, tl))
+    }
+}
+
+/// A query read from a line.
+enum Request {
+    Line(Query),
+
+    /// A line that is not a query, answered with an error in JSON,
+    /// so that there is one response per line.
+    Invalid(QueryError),
+
+    /// JSON-RPC request id, and its query.
+    Rpc(Value, Result<Query, json::RpcError>),
+}
+
+enum Response {
+    Line(Result<QueryResult, QueryError>),
+    Rpc(Value),
+}
+
+/// # Returns
+/// The request of the line, or `None` if it is not a query and is written as text.
+fn parse_request(line: &str, tokenizer: &dyn Tokenizer, format: Format) -> Option<Request> {
+    if format == Format::JsonRpc {
+        let (id, query) = json::request(line);
+        return Some(Request::Rpc(id, query));
+    }
+
+    match parser::query_with(line, tokenizer) {
+        Ok((_, q)) => Some(Request::Line(q)),
+        Err(_) if format == Format::Json && !line.trim().is_empty() => Some(Request::Invalid(
+            QueryError(format!("could not parse {}", line.trim())),
+        )),
+        Err(_) => None,
+    }
+}
+
+/// What to do with a query of a session.
+enum Step {
+    Run(Query),
+    Commit(Vec<Query>),
+    Answer(Result<QueryResult, QueryError>),
+}
+
+/// Opens and rolls back the transaction of the session,
+/// and stages the queries changing the list while it is open.
+fn step(q: Query, session: &mut Session) -> Step {
+    let no_transaction = || Step::Answer(Err(QueryError("no transaction begun".to_string())));
+    match (q, &mut session.staged) {
+        (Query::Begin, Some(_)) => {
+            Step::Answer(Err(QueryError("transaction already begun".to_string())))
+        }
+        (Query::Begin, staged) => {
+            *staged = Some(vec![]);
+            Step::Answer(Ok(QueryResult::Staged(0)))
+        }
+        (Query::Commit, staged) => match staged.take() {
+            Some(staged) => Step::Commit(staged),
+            None => no_transaction(),
+        },
+        (Query::Rollback, staged) => match staged.take() {
+            Some(staged) => Step::Answer(Ok(QueryResult::RolledBack(staged.len()))),
+            None => no_transaction(),
+        },
+        (q, Some(staged)) if !q.is_read_only() => {
+            staged.push(q);
+            Step::Answer(Ok(QueryResult::Staged(staged.len())))
         }
+        (q, _) => Step::Run(q),
     }
 }
 
-fn run_query(q: Query, tl: &mut TodoList) -> Result<QueryResult, QueryError> {
+/// Runs the staged queries on a snapshot of the list, which replaces the list
+/// only if they all succeed, so that the list and its indexes are changed all at once or not at all.
+pub fn commit(staged: Vec<Query>, tl: &mut TodoList) -> Result<QueryResult, QueryError> {
+    let mut committed = tl.snapshot();
+    let count = staged.len();
+    for q in staged {
+        if let Err(e) = run_query(q, &mut committed) {
+            return Err(QueryError(format!("transaction rolled back, {}", e.0)));
+        }
+    }
+
+    *tl = committed;
+    Ok(QueryResult::Committed(count))
+}
+
+fn run_request(
+    request: Request,
+    run: impl FnOnce(Query) -> Result<QueryResult, QueryError>,
+) -> Response {
+    match request {
+        Request::Line(q) => Response::Line(run(q)),
+        Request::Invalid(e) => Response::Line(Err(e)),
+        Request::Rpc(id, query) => {
+            let result = query.and_then(|q| run(q).map_err(json::RpcError::from));
+            Response::Rpc(json::response(id, result.as_ref()))
+        }
+    }
+}
+
+fn write_response(
+    response: Response,
+    out: &mut impl io::Write,
+    err: &mut impl io::Write,
+    options: Options,
+) {
+    let result = match response {
+        Response::Line(result) => result,
+        Response::Rpc(response) => {
+            writeln!(out, "{}", response).expect("could not write to out");
+            return;
+        }
+    };
+
+    match (result, options.format) {
+        (Ok(r), Format::Json) => {
+            writeln!(out, "{}", json::result(&r)).expect("could not write to out");
+        }
+        (Err(e), Format::Json) => {
+            writeln!(out, "{}", json::error(&e)).expect("could not write to out");
+        }
+        (Ok(r), _) if options.color => {
+            writeln!(out, "{:#}", r).expect("could not write to out");
+        }
+        (Ok(r), _) => {
+            writeln!(out, "{}", r).expect("could not write to out");
+        }
+        (Err(e), _) => {
+            writeln!(err, "Error: {}", e).expect("could not write to err");
+        }
+    }
+}
+
+pub fn run_query(q: Query, tl: &mut TodoList) -> Result<QueryResult, QueryError> {
     match q {
         Query::Add(desc, tags) => {
             let item = tl.push(desc, tags);
@@ -24,8 +275,81 @@ fn run_query(q: Query, tl: &mut TodoList) -> Result<QueryResult, QueryError> {
             .done_with_index(idx)
             .map(|_idx| QueryResult::Done)
             .ok_or(QueryError("item does not exist".to_string())),
-        Query::Search(params) => Ok(QueryResult::Found(
-            tl.search(params).into_iter().cloned().collect(),
-        )),
+        Query::RenameTag(from, to) | Query::MergeTags(from, to)
+            if from != to && from.includes(&to) =>
+        {
+            Err(QueryError("tag cannot move under itself".to_string()))
+        }
+        Query::RenameTag(from, to) => tl
+            .rename_tag(&from, &to)
+            .map(QueryResult::Updated)
+            .ok_or(QueryError("tag already exists".to_string())),
+        Query::MergeTags(from, into) => tl
+            .merge_tags(&from, &into)
+            .map(QueryResult::Updated)
+            .ok_or(QueryError("tag cannot move under itself".to_string())),
+        Query::DropTag(tag) => Ok(QueryResult::Updated(tl.drop_tag(&tag))),
+        Query::AddRule(rule) => Ok(QueryResult::Updated(tl.add_rule(rule))),
+        Query::DoneAll(selection) => {
+            let selected = tl.select(&selection);
+            Ok(QueryResult::Updated(tl.done_all(&selected)))
+        }
+        Query::TagAll(tags, selection) => {
+            let selected = tl.select(&selection);
+            Ok(QueryResult::Updated(tl.tag_all(&selected, &tags)))
+        }
+        Query::UntagAll(tags, selection) => {
+            let selected = tl.select(&selection);
+            Ok(QueryResult::Updated(tl.untag_all(&selected, &tags)))
+        }
+        Query::Begin | Query::Commit | Query::Rollback => {
+            Err(QueryError("transactions need a session".to_string()))
+        }
+        q => run_read_query(q, tl),
+    }
+}
+
+/// Runs a query that does not change the list.
+fn run_read_query(q: Query, tl: &TodoList) -> Result<QueryResult, QueryError> {
+    match q {
+        Query::Search(params) if params.group_by_tag => {
+            Ok(QueryResult::Groups(tl.facets(&tl.search(params))))
+        }
+        Query::Search(params) => {
+            let items = tl.search(params.clone());
+            Ok(QueryResult::Found {
+                facets: if params.facets {
+                    Some(tl.facets(&items))
+                } else {
+                    None
+                },
+                explanations: if params.explain {
+                    Some(tl.explain(&items, &params))
+                } else {
+                    None
+                },
+                highlights: if params.highlight {
+                    Some(tl.highlight(&items, &params))
+                } else {
+                    None
+                },
+                suggestions: if items.is_empty() {
+                    tl.corrections(&params)
+                } else {
+                    vec![]
+                },
+                items: items.into_iter().cloned().collect(),
+            })
+        }
+        Query::Count(params) => Ok(QueryResult::Count(tl.count(params))),
+        Query::Tags => Ok(QueryResult::Tags(tl.tag_tree())),
+        Query::TagStats(params) => Ok(QueryResult::Counts(tl.tag_counts(&params))),
+        Query::WordStats(params) => Ok(QueryResult::Counts(tl.word_counts(&params))),
+        Query::Suggest(prefix) => Ok(QueryResult::Counts(tl.suggest(&prefix, SUGGESTIONS))),
+        Query::Rules => Ok(QueryResult::Rules(tl.rules().to_vec())),
+        Query::Begin | Query::Rollback => {
+            Err(QueryError("transactions need a session".to_string()))
+        }
+        _ => Err(QueryError("query changes the list".to_string())),
     }
 }
@@ -0,0 +1,64 @@
+use std::io::{self, BufRead, BufReader};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::thread;
+
+use crate::runner::{self, Options, Session};
+use crate::*;
+
+/// Serves the list to the clients connecting to the TCP listener,
+/// each on its own thread, until accepting a connection fails.
+pub fn serve_tcp(listener: TcpListener, tl: SharedTodoList, options: Options) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        let reader = stream.try_clone()?;
+        spawn_client(reader, stream, &tl, options);
+    }
+}
+
+/// Serves the list to the clients connecting to the Unix socket listener,
+/// each on its own thread, until accepting a connection fails.
+#[cfg(unix)]
+pub fn serve_unix(listener: UnixListener, tl: SharedTodoList, options: Options) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        let reader = stream.try_clone()?;
+        spawn_client(reader, stream, &tl, options);
+    }
+}
+
+fn spawn_client(
+    reader: impl io::Read + Send + 'static,
+    writer: impl io::Write + Send + 'static,
+    tl: &SharedTodoList,
+    options: Options,
+) {
+    let tl = tl.clone();
+    thread::spawn(move || serve_client(reader, writer, &tl, options));
+}
+
+/// Answers each line the client sends, writing errors to the client too,
+/// until it disconnects, dropping the changes of a transaction it left open.
+fn serve_client(
+    reader: impl io::Read,
+    mut writer: impl io::Write,
+    tl: &SharedTodoList,
+    options: Options,
+) {
+    let mut session = Session::new();
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        let mut response = Vec::new();
+        let mut error = Vec::new();
+        runner::run_shared_line(&line, tl, &mut session, &mut response, &mut error, options);
+        response.extend(error);
+        if writer.write_all(&response).is_err() {
+            return;
+        }
+    }
+}
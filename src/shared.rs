@@ -0,0 +1,72 @@
+use std::sync::{Arc, RwLock};
+
+use crate::*;
+
+/// Handle to a todo list shared between threads, cloned to hand it to each of them.
+/// Searches run on a snapshot of the list, so they only hold the lock to take it,
+/// and writers keep applying changes while they run.
+#[derive(Debug, Clone)]
+pub struct SharedTodoList {
+    list: Arc<RwLock<TodoList>>,
+}
+
+impl SharedTodoList {
+    pub fn new(tl: TodoList) -> SharedTodoList {
+        SharedTodoList {
+            list: Arc::new(RwLock::new(tl)),
+        }
+    }
+
+    /// Reads the list, in parallel with other readers,
+    /// holding back writers until `f` returns, so long reads should use a snapshot instead.
+    pub fn read<R>(&self, f: impl FnOnce(&TodoList) -> R) -> R {
+        f(&self.list.read().expect("todo list lock poisoned"))
+    }
+
+    /// Changes the list, waiting for readers and other writers to finish.
+    pub fn write<R>(&self, f: impl FnOnce(&mut TodoList) -> R) -> R {
+        f(&mut self.list.write().expect("todo list lock poisoned"))
+    }
+
+    /// # Returns
+    /// The list as it is now, unaffected by later changes.
+    pub fn snapshot(&self) -> TodoList {
+        self.read(TodoList::snapshot)
+    }
+
+    /// # Returns
+    /// Copies of the items the search finds.
+    pub fn search(&self, sp: SearchParams) -> Vec<TodoItem> {
+        let tl = self.snapshot();
+        let found = tl.search(sp).into_iter().cloned().collect();
+        found
+    }
+
+    pub fn count(&self, sp: SearchParams) -> usize {
+        self.snapshot().count(sp)
+    }
+
+    pub fn get(&self, idx: Index) -> Option<TodoItem> {
+        self.read(|tl| tl.get(idx).cloned())
+    }
+
+    pub fn push(&self, description: Description, tags: Vec<Tag>) -> TodoItem {
+        self.write(|tl| tl.push(description, tags))
+    }
+
+    pub fn done_with_index(&self, idx: Index) -> Option<Index> {
+        self.write(|tl| tl.done_with_index(idx))
+    }
+}
+
+impl Default for SharedTodoList {
+    fn default() -> Self {
+        SharedTodoList::new(TodoList::new())
+    }
+}
+
+impl From<TodoList> for SharedTodoList {
+    fn from(tl: TodoList) -> Self {
+        SharedTodoList::new(tl)
+    }
+}
@@ -1,11 +1,22 @@
 use crate::*;
+use im::{OrdMap, Vector};
 use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::hash::Hash;
+use std::sync::Arc;
 
-type IndexMap<K> = std::collections::HashMap<K, Vec<u64>>;
+type IndexMap<K> = OrdMap<K, Vector<u64>>;
+type PostingMap = OrdMap<String, Vector<Posting>>;
+
+/// Maximum number of corrections suggested for each search word or tag.
+const MAX_CORRECTIONS: usize = 3;
+
+/// Number of most recent changes kept for `changes_since`.
+const MAX_CHANGES: usize = 1024;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Index(u64);
 
 impl Index {
@@ -25,6 +36,7 @@ impl fmt::Display for Index {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Description(String);
 
 impl Description {
@@ -44,6 +56,7 @@ impl fmt::Display for Description {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tag(String);
 
 impl Tag {
@@ -58,6 +71,21 @@ impl Tag {
     pub fn from_strings(ss: Vec<&str>) -> Vec<Tag> {
         ss.clone().into_iter().map(|s| Tag::new(s)).collect()
     }
+
+    /// # Returns
+    /// The tag one level up, if any.
+    /// Levels of a tag are separated by `/`, as in `#work/client-a/billing`.
+    pub fn parent(&self) -> Option<Tag> {
+        self.0.rfind('/').map(|i| Tag::new(&self.0[..i]))
+    }
+
+    /// # Returns
+    /// Whether the tag is this tag or one of its descendants.
+    pub fn includes(&self, tag: &Tag) -> bool {
+        tag.0
+            .strip_prefix(self.0.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
 }
 
 impl fmt::Display for Tag {
@@ -72,7 +100,17 @@ impl Hash for Tag {
     }
 }
 
+/// A tag with the number of active items in its subtree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TagNode {
+    pub tag: Tag,
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TodoItem {
     pub index: Index,
     pub description: Description,
@@ -104,53 +142,139 @@ impl fmt::Display for TodoItem {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChangeKind {
+    Added,
+    Done,
+
+    /// Tags of the item changed, by tag management or rules.
+    Edited,
+}
+
+/// A change applied to an item, with the item as it is after the change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Change {
+    /// Number of the change, starting from 1, in the order changes were applied.
+    pub seq: u64,
+    pub kind: ChangeKind,
+    pub item: TodoItem,
+}
+
+/// Positions of a word in the description of an item.
 #[derive(Debug, Clone, PartialEq, Eq)]
+struct Posting {
+    item: u64,
+    positions: Vec<usize>,
+}
+
+/// Items, indexes and changes are persistent data structures,
+/// so that clones share most of their memory, and are cheap enough
+/// to hand out as snapshots.
+#[derive(Debug, Clone)]
 pub struct TodoList {
     top_index: Index,
-    items: Vec<TodoItem>,
+    items: Vector<TodoItem>,
 
     /// Map of tag to index of active items with that tag.
     tags_index: IndexMap<Tag>,
 
-    /// Map of word to index of active items with that word.
-    word_index: IndexMap<String>,
+    /// Map of word to index of active items with that word, and its positions in them.
+    word_index: PostingMap,
+
+    /// Turns descriptions and search words into the terms of the word index.
+    analyzer: Arc<Analyzer>,
+
+    /// Rules tagging items automatically.
+    rules: Vec<Rule>,
+
+    /// Most recent changes, oldest first.
+    changes: Vector<Change>,
+
+    /// Number of the last change applied.
+    last_change: u64,
 }
 
 impl TodoList {
     pub fn new() -> TodoList {
-        TodoList {
-            top_index: Index::new(0),
-            items: vec![],
-            tags_index: IndexMap::new(),
-            word_index: IndexMap::new(),
-        }
+        TodoList::with_analyzer(Analyzer::new())
+    }
+
+    /// Items are stored in chunks allocated as they are pushed,
+    /// so the capacity is not reserved up front.
+    pub fn with_capacity(_capacity: usize) -> TodoList {
+        TodoList::new()
     }
 
-    pub fn with_capacity(capacity: usize) -> TodoList {
+    pub fn with_tokenizer(tokenizer: impl Tokenizer + 'static) -> TodoList {
+        TodoList::with_analyzer(Analyzer::new().with_tokenizer(tokenizer))
+    }
+
+    pub fn with_analyzer(analyzer: Analyzer) -> TodoList {
         TodoList {
             top_index: Index::new(0),
-            items: Vec::with_capacity(capacity),
+            items: Vector::new(),
             tags_index: IndexMap::new(),
-            word_index: IndexMap::new(),
+            word_index: PostingMap::new(),
+            analyzer: Arc::new(analyzer),
+            rules: vec![],
+            changes: Vector::new(),
+            last_change: 0,
         }
     }
 
-    pub fn push(&mut self, description: Description, tags: Vec<Tag>) -> TodoItem {
-        for tag in tags.iter() {
-            let entry = self.tags_index.entry(tag.clone()).or_insert(vec![]);
-            entry.push(self.top_index.value());
-        }
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    /// # Returns
+    /// A copy of the list, unaffected by later changes to it,
+    /// sharing the items and indexes the list does not change afterwards.
+    pub fn snapshot(&self) -> TodoList {
+        self.clone()
+    }
+
+    pub fn tokenizer(&self) -> &dyn Tokenizer {
+        self.analyzer.tokenizer()
+    }
 
-        for word in description.value().split(" ") {
-            let entry = self.word_index.entry(word.to_string()).or_insert(vec![]);
-            entry.push(self.top_index.value());
+    pub fn push(&mut self, description: Description, mut tags: Vec<Tag>) -> TodoItem {
+        apply_rules(&self.rules, &description, &mut tags);
+        let index = self.top_index;
+        self.insert(TodoItem::new(index, description, tags, false));
+        self.record(ChangeKind::Added, index);
+        self.items.back().cloned().unwrap()
+    }
+
+    /// Appends the item without its repeated tags, indexing it if it is active.
+    fn insert(&mut self, mut item: TodoItem) {
+        let mut seen = HashSet::new();
+        item.tags.retain(|tag| seen.insert(tag.clone()));
+
+        if !item.done {
+            for tag in item.tags.iter() {
+                let entry = self.tags_index.entry(tag.clone()).or_default();
+                entry.push_back(item.index.value());
+            }
+
+            for (term, positions) in terms(&self.analyzer, item.description.value()) {
+                let entry = self.word_index.entry(term).or_default();
+                entry.push_back(Posting {
+                    item: item.index.value(),
+                    positions,
+                });
+            }
         }
 
-        let item = TodoItem::new(self.top_index, description, tags, false);
-        self.items.push(item.clone());
+        self.items.push_back(item);
         self.top_index = Index::new(self.top_index.value() + 1);
+    }
 
-        item
+    /// # Returns
+    /// The item with the index, whether it is done or not.
+    pub fn get(&self, idx: Index) -> Option<&TodoItem> {
+        self.items.get(idx.value() as usize)
     }
 
     pub fn done_with_index(&mut self, idx: Index) -> Option<Index> {
@@ -168,28 +292,574 @@ impl TodoList {
                 .position(|id| *id == item.index.value())
                 .unwrap();
 
-            indices.swap_remove(idx);
+            swap_remove(indices, idx);
             if indices.is_empty() {
                 self.tags_index.remove(tag).unwrap();
             }
         }
 
-        for word in item.description.value().split(" ") {
-            let indices = self.word_index.get_mut(word).unwrap();
-            let idx = indices
+        for term in terms(&self.analyzer, item.description.value()).keys() {
+            let postings = self.word_index.get_mut(term).unwrap();
+            let idx = postings
                 .iter()
-                .position(|id| *id == item.index.value())
+                .position(|posting| posting.item == item.index.value())
                 .unwrap();
 
-            indices.swap_remove(idx);
-            if indices.is_empty() {
-                self.word_index.remove(word).unwrap();
+            swap_remove(postings, idx);
+            if postings.is_empty() {
+                self.word_index.remove(term).unwrap();
             }
         }
 
+        self.record(ChangeKind::Done, idx);
         Some(idx)
     }
 
+    /// # Returns
+    /// Indices of the existing items of the selection, in order.
+    pub fn select(&self, selection: &Selection) -> Vec<Index> {
+        match selection {
+            Selection::Ranges(ranges) => {
+                let last = self.top_index.value().checked_sub(1);
+                let mut indices = ranges
+                    .iter()
+                    .filter_map(|(start, end)| Some(start.value()..=end.value().min(last?)))
+                    .flatten()
+                    .map(Index::new)
+                    .collect::<Vec<_>>();
+
+                indices.sort_unstable();
+                indices.dedup();
+                indices
+            }
+            Selection::Where(sp) => {
+                let mut indices = self
+                    .search(sp.clone())
+                    .iter()
+                    .map(|item| item.index)
+                    .collect::<Vec<_>>();
+
+                indices.sort_unstable();
+                indices
+            }
+        }
+    }
+
+    /// Marks the items done.
+    ///
+    /// # Returns
+    /// Number of items that were not done yet.
+    pub fn done_all(&mut self, indices: &[Index]) -> usize {
+        let mut changed = 0;
+        for idx in indices {
+            if matches!(self.get(*idx), Some(item) if !item.done) {
+                self.done_with_index(*idx);
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+
+    /// Adds the tags, and the tags the rules imply, to the active items.
+    ///
+    /// # Returns
+    /// Number of items changed.
+    pub fn tag_all(&mut self, indices: &[Index], tags: &[Tag]) -> usize {
+        let mut changed = vec![];
+        for idx in indices {
+            let item = match self.items.get_mut(idx.value() as usize) {
+                Some(item) if !item.done => item,
+                _ => continue,
+            };
+
+            let tagged = item.tags.len();
+            for tag in tags {
+                if !item.tags.contains(tag) {
+                    item.tags.push(tag.clone());
+                }
+            }
+
+            apply_rules(&self.rules, &item.description, &mut item.tags);
+            for tag in item.tags[tagged..].iter() {
+                let entry = self.tags_index.entry(tag.clone()).or_default();
+                entry.push_back(item.index.value());
+            }
+
+            if item.tags.len() > tagged {
+                changed.push(item.index);
+            }
+        }
+
+        for idx in changed.iter() {
+            self.record(ChangeKind::Edited, *idx);
+        }
+
+        changed.len()
+    }
+
+    /// Removes the tags, and their descendants, from the active items.
+    ///
+    /// # Returns
+    /// Number of items changed.
+    pub fn untag_all(&mut self, indices: &[Index], tags: &[Tag]) -> usize {
+        let mut changed = vec![];
+        for idx in indices {
+            let item = match self.items.get_mut(idx.value() as usize) {
+                Some(item) if !item.done => item,
+                _ => continue,
+            };
+
+            let (removed, kept) = item
+                .tags
+                .drain(..)
+                .partition::<Vec<_>, _>(|tag| tags.iter().any(|t| t.includes(tag)));
+
+            item.tags = kept;
+            for tag in removed.iter() {
+                let indices = self.tags_index.get_mut(tag).unwrap();
+                let idx = indices
+                    .iter()
+                    .position(|id| *id == item.index.value())
+                    .unwrap();
+
+                swap_remove(indices, idx);
+                if indices.is_empty() {
+                    self.tags_index.remove(tag).unwrap();
+                }
+            }
+
+            if !removed.is_empty() {
+                changed.push(item.index);
+            }
+        }
+
+        for idx in changed.iter() {
+            self.record(ChangeKind::Edited, *idx);
+        }
+
+        changed.len()
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Adds the rule, and re-applies all rules to the active items.
+    ///
+    /// # Returns
+    /// Number of items changed.
+    pub fn add_rule(&mut self, rule: Rule) -> usize {
+        if !self.rules.contains(&rule) {
+            self.rules.push(rule);
+        }
+
+        let mut changed = vec![];
+        for item in self.items.iter_mut().filter(|item| !item.done) {
+            let tagged = item.tags.len();
+            if !apply_rules(&self.rules, &item.description, &mut item.tags) {
+                continue;
+            }
+
+            for tag in item.tags[tagged..].iter() {
+                let entry = self.tags_index.entry(tag.clone()).or_default();
+                entry.push_back(item.index.value());
+            }
+
+            changed.push(item.index);
+        }
+
+        for idx in changed.iter() {
+            self.record(ChangeKind::Edited, *idx);
+        }
+
+        changed.len()
+    }
+
+    /// # Returns
+    /// Number of the last change applied, 0 if there is none.
+    pub fn last_change(&self) -> u64 {
+        self.last_change
+    }
+
+    /// # Returns
+    /// Changes applied after the one numbered `seq`, oldest first,
+    /// or `None` if some of them are no longer kept, or `seq` is yet to come.
+    pub fn changes_since(&self, seq: u64) -> Option<Vec<Change>> {
+        let oldest = self.last_change - self.changes.len() as u64;
+        if seq < oldest || seq > self.last_change {
+            return None;
+        }
+
+        let skipped = (seq - oldest) as usize;
+        Some(self.changes.iter().skip(skipped).cloned().collect())
+    }
+
+    /// Records a change of the item, forgetting the oldest change if too many are kept.
+    fn record(&mut self, kind: ChangeKind, idx: Index) {
+        self.last_change += 1;
+        self.changes.push_back(Change {
+            seq: self.last_change,
+            kind,
+            item: self.items[idx.value() as usize].clone(),
+        });
+
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.pop_front();
+        }
+    }
+
+    /// # Returns
+    /// Tags of the active items, with the number of items carrying them, most frequent first.
+    pub fn facets(&self, items: &[&TodoItem]) -> Vec<(Tag, usize)> {
+        let indices = items
+            .iter()
+            .map(|item| item.index.value())
+            .collect::<HashSet<_>>();
+
+        let mut facets = self
+            .tags_index
+            .iter()
+            .filter_map(|(tag, postings)| {
+                let count = postings.iter().filter(|idx| indices.contains(idx)).count();
+                if count > 0 {
+                    Some((tag.clone(), count))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    /// Explains the items from the items themselves,
+    /// so that the explanation does not depend on how they were found.
+    ///
+    /// # Returns
+    /// Search terms each item matched, and the words or tags they matched.
+    pub fn explain(&self, items: &[&TodoItem], sp: &SearchParams) -> Vec<Explanation> {
+        let phrases = self.search_phrases(&sp.phrases);
+        let words = sp
+            .words
+            .iter()
+            .map(|word| (word, self.analyzer.analyze_query(&word.0)))
+            .collect::<Vec<_>>();
+
+        items
+            .iter()
+            .map(|item| {
+                let mut hits = vec![];
+                for tag in sp.tags.iter() {
+                    for t in item.tags.iter().filter(|t| tag.includes(t)) {
+                        hits.push(Hit::new(tag, t));
+                    }
+                }
+
+                for pattern in sp.tag_patterns.iter() {
+                    for t in item.tags.iter().filter(|t| pattern.matches(t)) {
+                        hits.push(Hit::new(pattern, t));
+                    }
+                }
+
+                let terms = self.analyzer.analyze_words(item.description.value());
+                for (word, targets) in words.iter() {
+                    let mut matched = terms
+                        .iter()
+                        .filter(|(_, _, term)| targets.iter().any(|t| matches_word(t, term)))
+                        .map(|(_, original, _)| *original)
+                        .collect::<Vec<_>>();
+
+                    matched.dedup();
+                    for original in matched {
+                        hits.push(Hit::new(&word.0, original));
+                    }
+                }
+
+                let positions = terms
+                    .iter()
+                    .map(|(position, _, term)| (*position, term.clone()))
+                    .collect::<Vec<_>>();
+
+                for (phrase, search_phrase) in phrases.iter().zip(sp.phrases.iter()) {
+                    if let Some((start, end)) = phrase.find(&positions) {
+                        let mut matched: Vec<(usize, &str)> = vec![];
+                        for (position, original, _) in terms.iter() {
+                            let in_span = *position >= start && *position <= end;
+                            if in_span && matched.last().map(|(p, _)| p) != Some(position) {
+                                matched.push((*position, original));
+                            }
+                        }
+
+                        let matched = matched
+                            .iter()
+                            .map(|(_, original)| *original)
+                            .collect::<Vec<_>>();
+
+                        hits.push(Hit::new(search_phrase, matched.join(" ")));
+                    }
+                }
+
+                for regex in sp.regexes.iter() {
+                    if let Some(matched) = regex.find(item.description.value()) {
+                        hits.push(Hit::new(regex, matched));
+                    }
+                }
+
+                Explanation {
+                    index: item.index,
+                    hits,
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the characters of each word the search words matched,
+    /// or the whole word when stemming changed the characters that matched.
+    ///
+    /// # Returns
+    /// Sorted, disjoint byte ranges of the highlighted characters of each item's description.
+    pub fn highlight(&self, items: &[&TodoItem], sp: &SearchParams) -> Vec<Highlight> {
+        let targets = self.search_terms(&sp.words);
+        items
+            .iter()
+            .map(|item| {
+                let text = item.description.value();
+                let mut ranges: Vec<(usize, usize)> = vec![];
+                for (_, word, term) in self.analyzer.analyze_words(text) {
+                    let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+                    for target in targets.iter().filter(|t| matches_word(t, &term)) {
+                        match matched_chars(target, word) {
+                            Some(chars) => ranges.extend(
+                                chars
+                                    .into_iter()
+                                    .map(|(start, end)| (offset + start, offset + end)),
+                            ),
+                            None => ranges.push((offset, offset + word.len())),
+                        }
+                    }
+                }
+
+                ranges.sort_unstable();
+                let mut merged: Vec<(usize, usize)> = vec![];
+                for (start, end) in ranges {
+                    match merged.last_mut() {
+                        Some((_, last_end)) if start <= *last_end => *last_end = end.max(*last_end),
+                        _ => merged.push((start, end)),
+                    }
+                }
+
+                Highlight {
+                    index: item.index,
+                    ranges: merged,
+                }
+            })
+            .collect()
+    }
+
+    /// # Returns
+    /// Tags with the number of active items carrying them.
+    pub fn tag_counts(&self, params: &StatsParams) -> Vec<(String, usize)> {
+        let prefix = params.prefix.as_deref().unwrap_or("");
+        let counts = self
+            .tags_index
+            .range(Tag::new(prefix)..)
+            .take_while(|(tag, _)| tag.value().starts_with(prefix))
+            .map(|(tag, indices)| (tag.to_string(), indices.len()))
+            .collect();
+
+        rank_counts(counts, params)
+    }
+
+    /// # Returns
+    /// Words of the word index with the number of active items containing them.
+    pub fn word_counts(&self, params: &StatsParams) -> Vec<(String, usize)> {
+        let prefix = params.prefix.as_deref().unwrap_or("");
+        let counts = self
+            .word_index
+            .range(prefix.to_string()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .map(|(word, postings)| (word.clone(), postings.len()))
+            .collect();
+
+        rank_counts(counts, params)
+    }
+
+    /// Completes a partial word, or a partial tag if the prefix starts with `#`.
+    ///
+    /// # Returns
+    /// At most `limit` completions with the number of active items containing them,
+    /// most frequent first.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<(String, usize)> {
+        let params = |prefix: &str| StatsParams {
+            order: StatsOrder::Count,
+            prefix: Some(prefix.to_string()),
+            limit: Some(limit),
+        };
+
+        match prefix.strip_prefix('#') {
+            Some(tag) => self.tag_counts(&params(tag)),
+            None => self.word_counts(&params(prefix)),
+        }
+    }
+
+    /// Finds the words and tags closest to the search words and tags,
+    /// for when a search finds nothing.
+    ///
+    /// # Returns
+    /// Up to `MAX_CORRECTIONS` words, and `#`-prefixed tags, for each search word and tag,
+    /// closest first, then most frequent first.
+    pub fn corrections(&self, sp: &SearchParams) -> Vec<String> {
+        let words = self
+            .word_index
+            .iter()
+            .map(|(word, postings)| (word.clone(), postings.len()));
+
+        let tags = self
+            .tags_index
+            .iter()
+            .map(|(tag, indices)| (tag.to_string(), indices.len()));
+
+        let candidates = words.chain(tags).collect::<Vec<_>>();
+        let searched = sp
+            .words
+            .iter()
+            .map(|word| word.0.clone())
+            .chain(sp.tags.iter().map(Tag::to_string));
+
+        let mut corrections: Vec<String> = vec![];
+        for searched in searched {
+            let is_tag = searched.starts_with('#');
+            let max_distance = ((searched.chars().count() + 1) / 3).clamp(1, 2);
+            let mut close = candidates
+                .iter()
+                .filter(|(candidate, _)| candidate.starts_with('#') == is_tag)
+                .map(|(candidate, count)| (edit_distance(&searched, candidate), candidate, count))
+                .filter(|(distance, _, _)| *distance > 0 && *distance <= max_distance)
+                .collect::<Vec<_>>();
+
+            close.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(a.2)).then(a.1.cmp(b.1)));
+            for (_, candidate, _) in close.into_iter().take(MAX_CORRECTIONS) {
+                if !corrections.contains(candidate) {
+                    corrections.push(candidate.clone());
+                }
+            }
+        }
+
+        corrections
+    }
+
+    /// Renames the tag, and its descendants, on every item.
+    ///
+    /// # Returns
+    /// Number of items changed, or `None` if the new tag is already in use
+    /// or is a descendant of the old one.
+    pub fn rename_tag(&mut self, from: &Tag, to: &Tag) -> Option<usize> {
+        if from == to {
+            return Some(0);
+        }
+
+        let in_use = self
+            .items
+            .iter()
+            .any(|item| item.tags.iter().any(|tag| to.includes(tag)));
+
+        if in_use || from.includes(to) {
+            return None;
+        }
+
+        Some(self.retag(from, Some(to)))
+    }
+
+    /// Replaces the first tag, and its descendants, with the second one on every item.
+    ///
+    /// # Returns
+    /// Number of items changed, or `None` if the second tag is a descendant of the first one.
+    pub fn merge_tags(&mut self, from: &Tag, into: &Tag) -> Option<usize> {
+        if from == into {
+            return Some(0);
+        }
+
+        if from.includes(into) {
+            return None;
+        }
+
+        Some(self.retag(from, Some(into)))
+    }
+
+    /// Removes the tag, and its descendants, from every item.
+    ///
+    /// # Returns
+    /// Number of items changed.
+    pub fn drop_tag(&mut self, tag: &Tag) -> usize {
+        self.retag(tag, None)
+    }
+
+    /// Moves the tag's subtree under the new tag, or removes it if there is none.
+    ///
+    /// # Returns
+    /// Number of items changed.
+    fn retag(&mut self, from: &Tag, to: Option<&Tag>) -> usize {
+        let retagged = |tag: &Tag| to.map(|to| Tag(format!("{}{}", to.0, &tag.0[from.0.len()..])));
+
+        let mut changed = vec![];
+        for item in self.items.iter_mut() {
+            if !item.tags.iter().any(|tag| from.includes(tag)) {
+                continue;
+            }
+
+            let mut tags: Vec<Tag> = vec![];
+            for tag in item.tags.iter() {
+                let tag = if from.includes(tag) {
+                    retagged(tag)
+                } else {
+                    Some(tag.clone())
+                };
+
+                if let Some(tag) = tag {
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+            }
+
+            item.tags = tags;
+            changed.push(item.index);
+        }
+
+        let moved = self
+            .tag_subtree(from)
+            .map(|(tag, _)| tag.clone())
+            .collect::<Vec<_>>();
+
+        let mut postings = vec![];
+        for tag in moved {
+            let indices = self.tags_index.remove(&tag).unwrap();
+            postings.push((tag, indices));
+        }
+
+        for (tag, indices) in postings {
+            if let Some(tag) = retagged(&tag) {
+                let entry = self.tags_index.entry(tag).or_default();
+                let mut merged = entry
+                    .iter()
+                    .chain(indices.iter())
+                    .copied()
+                    .collect::<Vec<_>>();
+                merged.sort_unstable();
+                merged.dedup();
+                *entry = merged.into_iter().collect();
+            }
+        }
+
+        for idx in changed.iter() {
+            self.record(ChangeKind::Edited, *idx);
+        }
+
+        changed.len()
+    }
+
     pub fn search(&self, sp: SearchParams) -> Vec<&TodoItem> {
         if self.items.len() < 1500 {
             self.search_iter(sp)
@@ -198,8 +868,42 @@ impl TodoList {
         }
     }
 
+    /// # Returns
+    /// Number of items the search finds.
+    pub fn count(&self, sp: SearchParams) -> usize {
+        match self.count_with_index(&sp) {
+            Some(count) => count,
+            None => self.search(sp).len(),
+        }
+    }
+
+    /// Counts using the length of a posting list,
+    /// which is possible when searching for a single tag without descendants.
+    ///
+    /// # Returns
+    /// Number of items the search finds, or `None` if it can not be counted this way.
+    fn count_with_index(&self, sp: &SearchParams) -> Option<usize> {
+        let only_tags = sp.words.is_empty()
+            && sp.phrases.is_empty()
+            && sp.regexes.is_empty()
+            && sp.tag_patterns.is_empty();
+
+        if !only_tags || sp.tags.len() != 1 {
+            return None;
+        }
+
+        let mut subtree = self.tag_subtree(&sp.tags[0]);
+        match (subtree.next(), subtree.next()) {
+            (None, _) => Some(0),
+            (Some((_, indices)), None) => Some(indices.len()),
+            _ => None,
+        }
+    }
+
     /// Searches iterating over items.
     pub fn search_iter(&self, sp: SearchParams) -> Vec<&TodoItem> {
+        let targets = self.search_terms(&sp.words);
+        let phrases = self.search_phrases(&sp.phrases);
         self.items
             .iter()
             .filter(|item| {
@@ -207,13 +911,37 @@ impl TodoList {
                     return false;
                 }
 
-                if sp.tags.iter().any(|tag| item.tags.contains(tag)) {
+                if sp
+                    .tags
+                    .iter()
+                    .any(|tag| item.tags.iter().any(|t| tag.includes(t)))
+                {
                     return true;
                 }
 
-                sp.words
+                if sp
+                    .tag_patterns
                     .iter()
-                    .any(|word| contains_word(&word.0, item.description.value()))
+                    .any(|pattern| item.tags.iter().any(|tag| pattern.matches(tag)))
+                {
+                    return true;
+                }
+
+                if sp
+                    .regexes
+                    .iter()
+                    .any(|r| r.is_match(item.description.value()))
+                {
+                    return true;
+                }
+
+                if targets.is_empty() && phrases.is_empty() {
+                    return false;
+                }
+
+                let terms = self.analyzer.analyze_positions(item.description.value());
+                contains_word(&targets, &terms)
+                    || phrases.iter().any(|phrase| phrase.matches(&terms))
             })
             .collect()
     }
@@ -221,7 +949,10 @@ impl TodoList {
     /// Searches utilizing the tags index.
     pub fn search_with_index(&self, sp: SearchParams) -> Vec<&TodoItem> {
         let mut matches = self.search_index_tags(&sp.tags);
-        matches.extend(self.search_index_words(&sp.words));
+        matches.extend(self.search_index_tag_patterns(&sp.tag_patterns));
+        matches.extend(self.search_index_words(&self.search_terms(&sp.words)));
+        matches.extend(self.search_index_phrases(&self.search_phrases(&sp.phrases)));
+        matches.extend(self.search_index_regexes(&sp.regexes));
 
         matches.sort();
         matches.dedup();
@@ -250,8 +981,60 @@ impl TodoList {
 
         search
             .par_iter()
-            .filter_map(|tag| self.tags_index.get(tag))
-            .flatten()
+            .flat_map_iter(|tag| self.tag_subtree(tag).flat_map(|(_, indices)| indices))
+            .collect()
+    }
+
+    /// # Returns
+    /// Entries of the tags index for the tag and its descendants.
+    fn tag_subtree<'a>(&'a self, tag: &Tag) -> impl Iterator<Item = (&'a Tag, &'a Vector<u64>)> {
+        let descendants = Tag(format!("{}/", tag.0));
+        self.tags_index.get_key_value(tag).into_iter().chain(
+            self.tags_index
+                .range(descendants.clone()..)
+                .take_while(move |(t, _)| t.0.starts_with(&descendants.0)),
+        )
+    }
+
+    /// # Returns
+    /// Tags of active items arranged by level, with the number of items in each subtree.
+    pub fn tag_tree(&self) -> Vec<TagNode> {
+        let mut subtrees: BTreeMap<Tag, HashSet<u64>> = BTreeMap::new();
+        for (tag, indices) in self.tags_index.iter() {
+            let mut ancestor = Some(tag.clone());
+            while let Some(tag) = ancestor {
+                ancestor = tag.parent();
+                subtrees.entry(tag).or_default().extend(indices.iter());
+            }
+        }
+
+        let mut children: HashMap<Option<Tag>, Vec<(Tag, usize)>> = HashMap::new();
+        for (tag, indices) in subtrees {
+            children
+                .entry(tag.parent())
+                .or_default()
+                .push((tag, indices.len()));
+        }
+
+        tag_nodes(None, &children)
+    }
+
+    /// Filters items by tag pattern.
+    /// Returns indices of items with a tag matching at least one pattern.
+    ///
+    /// # Returns
+    /// Item indices.
+    fn search_index_tag_patterns(&self, search: &[TagPattern]) -> Vec<&u64> {
+        search
+            .par_iter()
+            .flat_map_iter(|pattern| {
+                let prefix = pattern.prefix();
+                self.tags_index
+                    .range(Tag::new(prefix)..)
+                    .take_while(move |(tag, _)| tag.value().starts_with(prefix))
+                    .filter(move |(tag, _)| pattern.matches(tag))
+                    .flat_map(|(_, indices)| indices)
+            })
             .collect()
     }
 
@@ -260,32 +1043,312 @@ impl TodoList {
     ///
     /// # Returns
     /// Item indices.
-    fn search_index_words(&self, search: &Vec<SearchWord>) -> Vec<&u64> {
+    fn search_index_words(&self, search: &[String]) -> Vec<&u64> {
         if search.is_empty() {
             return vec![];
         }
 
         self.word_index
-            .par_iter()
-            .filter_map(|(key, values)| {
-                if search.iter().any(|target| matches_word(&target.0, key)) {
-                    Some(values)
+            .iter()
+            .par_bridge()
+            .filter_map(|(key, postings)| {
+                if search.iter().any(|target| matches_word(target, key)) {
+                    Some(postings)
                 } else {
                     None
                 }
             })
-            .flatten()
+            .flat_map_iter(|postings| postings.iter().map(|posting| &posting.item))
             .collect()
     }
+
+    /// Filters items by phrase.
+    /// Returns indices of items that match at least one phrase.
+    ///
+    /// # Returns
+    /// Item indices.
+    fn search_index_phrases(&self, search: &[Phrase]) -> Vec<&u64> {
+        search
+            .par_iter()
+            .flat_map(|phrase| self.search_index_phrase(phrase))
+            .collect()
+    }
+
+    /// # Returns
+    /// Indices of items containing the phrase.
+    fn search_index_phrase(&self, phrase: &Phrase) -> Vec<&u64> {
+        if phrase.is_empty() {
+            return vec![];
+        }
+
+        // Positions of each group of alternative terms, by item.
+        let groups = phrase
+            .terms()
+            .map(|alternatives| {
+                let mut positions: HashMap<&u64, Vec<usize>> = HashMap::new();
+                for posting in alternatives
+                    .iter()
+                    .filter_map(|term| self.word_index.get(term))
+                    .flatten()
+                {
+                    let entry = positions.entry(&posting.item).or_insert(vec![]);
+                    entry.extend(posting.positions.iter());
+                }
+
+                positions.values_mut().for_each(|p| p.sort_unstable());
+                positions
+            })
+            .collect::<Vec<_>>();
+
+        groups[0]
+            .keys()
+            .filter(|item| {
+                groups
+                    .iter()
+                    .map(|group| group.get(*item).map(Vec::as_slice))
+                    .collect::<Option<Vec<_>>>()
+                    .is_some_and(|positions| phrase.matches_positions(&positions))
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Filters items by regular expression.
+    /// Returns indices of items that match at least one regular expression.
+    ///
+    /// # Returns
+    /// Item indices.
+    fn search_index_regexes(&self, search: &[SearchRegex]) -> Vec<&u64> {
+        search
+            .par_iter()
+            .flat_map(|regex| self.search_index_regex(regex))
+            .collect()
+    }
+
+    /// Narrows candidates down to items with a word containing a literal prefix of the regex,
+    /// scanning all active items if there is none.
+    ///
+    /// # Returns
+    /// Indices of items matching the regex.
+    fn search_index_regex(&self, regex: &SearchRegex) -> Vec<&u64> {
+        let fragments = match self.regex_fragments(regex) {
+            Some(fragments) => fragments,
+            None => {
+                return self
+                    .items
+                    .par_iter()
+                    .filter(|item| !item.done && regex.is_match(item.description.value()))
+                    .map(|item| &item.index.0)
+                    .collect();
+            }
+        };
+
+        let mut candidates = self
+            .word_index
+            .iter()
+            .par_bridge()
+            .filter(|(key, _)| fragments.iter().any(|fragment| key.contains(fragment)))
+            .flat_map(|(_, postings)| postings.par_iter().map(|posting| &posting.item))
+            .collect::<Vec<_>>();
+
+        candidates.sort();
+        candidates.dedup();
+        candidates.retain(|idx| regex.is_match(self.items[**idx as usize].description.value()));
+        candidates
+    }
+
+    /// # Returns
+    /// Parts of words, one of which every match of the regex contains,
+    /// or `None` if the word index can not tell.
+    fn regex_fragments<'a>(&self, regex: &'a SearchRegex) -> Option<Vec<&'a str>> {
+        if !self.analyzer.preserves_words() {
+            return None;
+        }
+
+        regex
+            .prefixes()?
+            .iter()
+            .map(|prefix| {
+                // Alphanumeric ASCII characters always belong to a single word.
+                let end = prefix
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(prefix.len());
+
+                if end == 0 {
+                    None
+                } else {
+                    Some(&prefix[..end])
+                }
+            })
+            .collect()
+    }
+
+    /// # Returns
+    /// Terms the search words are matched by.
+    fn search_terms(&self, words: &[SearchWord]) -> Vec<String> {
+        words
+            .iter()
+            .flat_map(|word| self.analyzer.analyze_query(&word.0))
+            .collect()
+    }
+
+    fn search_phrases(&self, phrases: &[SearchPhrase]) -> Vec<Phrase> {
+        phrases
+            .iter()
+            .map(|phrase| Phrase::new(&self.analyzer, phrase))
+            .collect()
+    }
+}
+
+/// Lists are equal when their items and rules are,
+/// whatever the order of the postings of the indexes derived from them.
+impl PartialEq for TodoList {
+    fn eq(&self, other: &Self) -> bool {
+        self.top_index == other.top_index && self.items == other.items && self.rules == other.rules
+    }
+}
+
+impl Eq for TodoList {}
+
+/// Serialized form of a todo list, without the indexes derived from the items.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedTodoList<I, R> {
+    items: I,
+    rules: R,
+}
+
+/// Serializes the items and rules only.
+/// The analyzer is not serialized either.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TodoList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedTodoList {
+            items: &self.items,
+            rules: &self.rules,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Rebuilds the indexes from the items, analyzing them with the default analyzer.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TodoList {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let list: SerializedTodoList<Vec<TodoItem>, Vec<Rule>> =
+            serde::Deserialize::deserialize(deserializer)?;
+
+        let mut tl = TodoList::new();
+        tl.rules = list.rules;
+        for item in list.items {
+            if item.index != tl.top_index {
+                return Err(serde::de::Error::custom(format!(
+                    "expected item {}, found item {}",
+                    tl.top_index, item.index
+                )));
+            }
+
+            tl.insert(item);
+        }
+
+        Ok(tl)
+    }
+}
+
+/// Removes the element by replacing it with the last one.
+fn swap_remove<T: Clone>(vector: &mut Vector<T>, idx: usize) {
+    let last = vector.len() - 1;
+    vector.swap(idx, last);
+    vector.pop_back();
+}
+
+/// Sorts and truncates the counts as requested.
+fn rank_counts(mut counts: Vec<(String, usize)>, params: &StatsParams) -> Vec<(String, usize)> {
+    match params.order {
+        StatsOrder::Count => counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+        StatsOrder::Name => counts.sort(),
+    }
+
+    if let Some(limit) = params.limit {
+        counts.truncate(limit);
+    }
+
+    counts
+}
+
+/// # Returns
+/// Nodes of the children of the tag, or of the top level tags.
+fn tag_nodes(
+    parent: Option<Tag>,
+    children: &HashMap<Option<Tag>, Vec<(Tag, usize)>>,
+) -> Vec<TagNode> {
+    children
+        .get(&parent)
+        .map(|tags| {
+            tags.iter()
+                .map(|(tag, count)| TagNode {
+                    tag: tag.clone(),
+                    count: *count,
+                    children: tag_nodes(Some(tag.clone()), children),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// # Returns
+/// Distinct terms of the text, as indexed, with their positions.
+fn terms(analyzer: &Analyzer, text: &str) -> BTreeMap<String, Vec<usize>> {
+    let mut terms: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (position, term) in analyzer.analyze_positions(text) {
+        let positions = terms.entry(term).or_insert(vec![]);
+        if positions.last() != Some(&position) {
+            positions.push(position);
+        }
+    }
+
+    terms
+}
+
+/// # Returns
+/// Whether the terms contain any of the targets as a substring.
+fn contains_word(targets: &[String], terms: &[(usize, String)]) -> bool {
+    terms
+        .iter()
+        .any(|(_, term)| targets.iter().any(|target| matches_word(target, term)))
+}
+
+/// # Returns
+/// Number of single character insertions, deletions and substitutions turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
 }
 
 /// # Returns
-/// Whether the haystack contains the target as a substring.
-fn contains_word(target: impl AsRef<str>, haystack: impl AsRef<str>) -> bool {
-    haystack
-        .as_ref()
-        .split(" ")
-        .any(|word| matches_word(&target, word))
+/// Byte ranges of the characters of the word matching the target's characters in order,
+/// leftmost first, or `None` if the word does not contain them.
+fn matched_chars(target: &str, word: &str) -> Option<Vec<(usize, usize)>> {
+    let mut word_chars = word.char_indices();
+    target
+        .chars()
+        .map(|target_char| {
+            word_chars
+                .find(|(_, word_char)| *word_char == target_char)
+                .map(|(start, word_char)| (start, start + word_char.len_utf8()))
+        })
+        .collect()
 }
 
 /// # Returns
@@ -0,0 +1,134 @@
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits text into the words used to build and query the word index.
+pub trait Tokenizer: fmt::Debug + Send + Sync {
+    /// # Returns
+    /// Words of the text, in order of appearance.
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str>;
+
+    /// # Returns
+    /// Words of the text, in order of appearance, with their word offset.
+    /// Words sharing a position are alternatives for the same part of the text.
+    fn tokenize_positions<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        self.tokenize(text).into_iter().enumerate().collect()
+    }
+
+    /// # Returns
+    /// Words of a searched text, which should not match more than the text itself.
+    fn tokenize_query<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        self.tokenize(text)
+    }
+}
+
+/// How hyphenated words, such as `follow-up`, are tokenized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hyphens {
+    /// Keep hyphenated words whole.
+    Keep,
+
+    /// Split hyphenated words into their parts.
+    Split,
+
+    /// Emit the whole hyphenated word followed by its parts.
+    Both,
+}
+
+/// Tokenizer using Unicode word segmentation (UAX #29).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnicodeTokenizer {
+    hyphens: Hyphens,
+}
+
+impl UnicodeTokenizer {
+    pub fn new() -> UnicodeTokenizer {
+        UnicodeTokenizer {
+            hyphens: Hyphens::Both,
+        }
+    }
+
+    pub fn with_hyphens(hyphens: Hyphens) -> UnicodeTokenizer {
+        UnicodeTokenizer { hyphens }
+    }
+
+    pub fn hyphens(&self) -> Hyphens {
+        self.hyphens
+    }
+
+    /// Pushes the tokens of hyphen-joined words.
+    /// A whole hyphenated word shares its position with its first part.
+    fn push_compound<'a>(
+        &self,
+        text: &'a str,
+        compound: &[(usize, &'a str)],
+        tokens: &mut Vec<(usize, &'a str)>,
+    ) {
+        let (first, last) = match (compound.first(), compound.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
+
+        let position = tokens.last().map_or(0, |(position, _)| position + 1);
+        if compound.len() == 1 {
+            tokens.push((position, first.1));
+            return;
+        }
+
+        if self.hyphens != Hyphens::Split {
+            tokens.push((position, &text[first.0..last.0 + last.1.len()]));
+        }
+
+        if self.hyphens != Hyphens::Keep {
+            tokens.extend(
+                compound
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, (_, word))| (position + offset, *word)),
+            );
+        }
+    }
+}
+
+impl Default for UnicodeTokenizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        self.tokenize_positions(text)
+            .into_iter()
+            .map(|(_, word)| word)
+            .collect()
+    }
+
+    fn tokenize_positions<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        let mut tokens = vec![];
+
+        // Words directly joined by a single hyphen form a compound.
+        let mut compound: Vec<(usize, &str)> = vec![];
+        for (start, word) in text.unicode_word_indices() {
+            if let Some((prev_start, prev)) = compound.last() {
+                if &text[prev_start + prev.len()..start] != "-" {
+                    self.push_compound(text, &compound, &mut tokens);
+                    compound.clear();
+                }
+            }
+
+            compound.push((start, word));
+        }
+
+        self.push_compound(text, &compound, &mut tokens);
+        tokens
+    }
+
+    /// Keeps hyphenated words whole when they are also indexed whole,
+    /// so that searching `follow-up` does not find every `up`.
+    fn tokenize_query<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        match self.hyphens {
+            Hyphens::Both => UnicodeTokenizer::with_hyphens(Hyphens::Keep).tokenize(text),
+            _ => self.tokenize(text),
+        }
+    }
+}
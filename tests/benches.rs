@@ -381,10 +381,7 @@ impl QuerySearch {
             .map(|word| todo::SearchWord(word.clone()))
             .collect();
 
-        todo::SearchParams {
-            words,
-            tags: self.tags.clone(),
-        }
+        todo::SearchParams::new(words, self.tags.clone())
     }
 }
 
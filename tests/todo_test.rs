@@ -5,6 +5,7 @@ use fake::{
 use rand::prelude::*;
 use std::{fmt, fs};
 use todo_swamp as todo;
+use todo_swamp::Tokenizer;
 
 const MAX_INPUT: usize = 5_000_000;
 
@@ -149,6 +150,1284 @@ fn basic() {
     assert_eq!(out, expected);
 }
 
+#[test]
+fn tokenizer() {
+    let tokenizer = todo::UnicodeTokenizer::new();
+    assert_eq!(
+        tokenizer.tokenize("buy  milk\tand follow-up"),
+        vec!["buy", "milk", "and", "follow-up", "follow", "up"]
+    );
+
+    assert_eq!(tokenizer.tokenize_query("follow-up"), vec!["follow-up"]);
+
+    let split = todo::UnicodeTokenizer::with_hyphens(todo::Hyphens::Split);
+    assert_eq!(split.tokenize("follow-up"), vec!["follow", "up"]);
+
+    let keep = todo::UnicodeTokenizer::with_hyphens(todo::Hyphens::Keep);
+    assert_eq!(keep.tokenize("follow-up"), vec!["follow-up"]);
+
+    let mut todos = todo::TodoList::new();
+    todos.push(todo::Description::new("call  the\tplumber"), vec![]);
+    todos.push(todo::Description::new("schedule follow-up"), vec![]);
+    todos.push(todo::Description::new("pick up kids"), vec![]);
+
+    let search = |todos: &todo::TodoList, word: &str| {
+        let (_, query) =
+            todo::parser::query_with(&format!("search {}", word), todos.tokenizer()).unwrap();
+        match query {
+            todo::Query::Search(params) => (
+                todos.search_iter(params.clone()).len(),
+                todos.search_with_index(params).len(),
+            ),
+            _ => unreachable!(),
+        }
+    };
+
+    assert_eq!(search(&todos, "plumber"), (1, 1));
+    assert_eq!(search(&todos, "up"), (2, 2));
+    assert_eq!(search(&todos, "follow-up"), (1, 1));
+    assert_eq!(search(&todos, "zzz"), (0, 0));
+
+    todos.done_with_index(todo::Index::new(1));
+    assert_eq!(search(&todos, "up"), (1, 1));
+    assert_eq!(search(&todos, "follow-up"), (0, 0));
+}
+
+#[test]
+fn analyzer() {
+    let analyzer = todo::Analyzer::new()
+        .with_stemming(todo::Algorithm::English)
+        .with_stop_words(todo::ENGLISH_STOP_WORDS)
+        .with_synonyms(&["groceries", "shopping"]);
+
+    assert_eq!(analyzer.analyze("buying the milk"), vec!["buy", "milk"]);
+
+    let mut todos = todo::TodoList::with_analyzer(analyzer);
+    todos.push(todo::Description::new("buying the milk"), vec![]);
+    todos.push(todo::Description::new("do the shopping"), vec![]);
+
+    let search = |todos: &todo::TodoList, words: Vec<&str>| {
+        let params = todo::SearchParams::new(
+            words.into_iter().map(todo::SearchWord::new).collect(),
+            vec![],
+        );
+
+        (
+            todos.search_iter(params.clone()).len(),
+            todos.search_with_index(params).len(),
+        )
+    };
+
+    assert_eq!(search(&todos, vec!["buy"]), (1, 1));
+    assert_eq!(search(&todos, vec!["groceries"]), (1, 1));
+    assert_eq!(search(&todos, vec!["the"]), (0, 0));
+    assert!(todos
+        .word_counts(&todo::StatsParams::new())
+        .iter()
+        .all(|(term, _)| term != "groceri"));
+    assert_eq!(todos.suggest("gro", 10), vec![]);
+
+    todos.done_with_index(todo::Index::new(1));
+    assert_eq!(search(&todos, vec!["groceries"]), (0, 0));
+}
+
+/// Searches both by iterating and with the index, checking that they agree.
+///
+/// # Returns
+/// Sorted indices of the items found.
+fn search_both(todos: &todo::TodoList, query: &str) -> Vec<u64> {
+    let (_, query) = todo::parser::query(query).unwrap();
+    match query {
+        todo::Query::Search(params) => {
+            let mut iter = todos
+                .search_iter(params.clone())
+                .into_iter()
+                .map(|item| item.index.value())
+                .collect::<Vec<_>>();
+
+            let mut index = todos
+                .search_with_index(params)
+                .into_iter()
+                .map(|item| item.index.value())
+                .collect::<Vec<_>>();
+
+            iter.sort();
+            index.sort();
+            assert_eq!(iter, index);
+            iter
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn phrases() {
+    let mut todos = todo::TodoList::new();
+    todos.push(todo::Description::new("buy milk and bread"), vec![]);
+    todos.push(todo::Description::new("buy fresh milk"), vec![]);
+    todos.push(todo::Description::new("milk the cow then buy hay"), vec![]);
+
+    assert_eq!(search_both(&todos, r#"search "buy milk""#), vec![0]);
+    assert_eq!(search_both(&todos, r#"search "buy milk"~1"#), vec![0, 1]);
+    assert_eq!(search_both(&todos, r#"search "milk buy"~5"#), vec![2]);
+    assert_eq!(
+        search_both(&todos, r#"search "buy milk"~18446744073709551615"#),
+        vec![0, 1]
+    );
+    assert!(
+        todo::parser::query(r#"search "buy milk"~99999999999999999999"#)
+            .map_or(true, |(rest, _)| !rest.is_empty())
+    );
+    assert_eq!(
+        search_both(&todos, r#"search "milk bread" #none"#),
+        Vec::<u64>::new()
+    );
+}
+
+#[test]
+fn regexes() {
+    let mut todos = todo::TodoList::new();
+    todos.push(todo::Description::new("pay invoice 42"), vec![]);
+    todos.push(todo::Description::new("email the accountant"), vec![]);
+    todos.push(todo::Description::new("file invoices"), vec![]);
+
+    assert_eq!(search_both(&todos, "search /invoice \\d+/"), vec![0]);
+    assert_eq!(search_both(&todos, "search /voices?$/"), vec![2]);
+    assert_eq!(search_both(&todos, "search /^e.*t$/"), vec![1]);
+    assert_eq!(search_both(&todos, "search /a\\/b/"), Vec::<u64>::new());
+    assert!(todo::SearchRegex::new("(").is_err());
+}
+
+#[test]
+fn tag_patterns() {
+    let pattern = todo::TagPattern::new("*-urgent");
+    assert!(pattern.matches(&todo::Tag::new("work-urgent")));
+    assert!(!pattern.matches(&todo::Tag::new("urgent")));
+    assert!(todo::TagPattern::new("gr?c*").matches(&todo::Tag::new("groceries")));
+
+    let mut todos = todo::TodoList::new();
+    todos.push(
+        todo::Description::new("buy bread"),
+        todo::Tag::from_strings(vec!["groceries"]),
+    );
+    todos.push(
+        todo::Description::new("fix server"),
+        todo::Tag::from_strings(vec!["work-urgent"]),
+    );
+    todos.push(
+        todo::Description::new("call mum"),
+        todo::Tag::from_strings(vec!["home-urgent", "family"]),
+    );
+
+    assert_eq!(search_both(&todos, "search #groc*"), vec![0]);
+    assert_eq!(search_both(&todos, "search #*-urgent"), vec![1, 2]);
+    assert_eq!(search_both(&todos, "search #*mil*"), vec![2]);
+    assert_eq!(search_both(&todos, "search #work-*"), vec![1]);
+}
+
+#[test]
+fn hierarchical_tags() {
+    let input = [
+        r#"add "send invoice" #work/client-a/billing"#,
+        r#"add "review contract" #work/client-a #legal"#,
+        r#"add "plan sprint" #work/internal"#,
+        r#"add "water plants" #work-shop"#,
+        "search #work/client-a",
+        "search #work",
+        "tags",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "3",
+        "2 item(s) found",
+        r#"1 "review contract" #work/client-a #legal"#,
+        r#"0 "send invoice" #work/client-a/billing"#,
+        "3 item(s) found",
+        r#"2 "plan sprint" #work/internal"#,
+        r#"1 "review contract" #work/client-a #legal"#,
+        r#"0 "send invoice" #work/client-a/billing"#,
+        "6 tag(s) found",
+        "#legal (1)",
+        "#work (3)",
+        "  #work/client-a (2)",
+        "    #work/client-a/billing (1)",
+        "  #work/internal (1)",
+        "#work-shop (1)",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+}
+
+#[test]
+fn tag_management() {
+    let input = vec![
+        r#"add "buy bread" #grocerys"#,
+        r#"add "buy milk" #grocerys #shopping"#,
+        r#"add "fix sink" #home/kitchen"#,
+        "done 0",
+        "rename-tag #grocerys #groceries",
+        "rename-tag #groceries #shopping",
+        "merge-tags #shopping #groceries",
+        "search #groceries",
+        "rename-tag #home #house",
+        "search #house/kitchen",
+        "drop-tag #house",
+        "search #house",
+        r#"add "x" #b #b"#,
+        r#"add "y" #a"#,
+        "merge-tags #a #b",
+        "done 3",
+        "search #b",
+        "merge-tags #b #b/c",
+        "rename-tag #b #b/c",
+        "search #b",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "done",
+        "2 item(s) updated",
+        "1 item(s) updated",
+        "1 item(s) found",
+        r#"1 "buy milk" #groceries"#,
+        "1 item(s) updated",
+        "1 item(s) found",
+        r#"2 "fix sink" #house/kitchen"#,
+        "1 item(s) updated",
+        "0 item(s) found",
+        "3",
+        "4",
+        "1 item(s) updated",
+        "done",
+        "1 item(s) found",
+        r#"4 "y" #b"#,
+        "1 item(s) found",
+        r#"4 "y" #b"#,
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+    assert_eq!(
+        String::from_utf8(err).unwrap(),
+        [
+            "Error: An error occurred while processing the query: tag already exists.",
+            "Error: An error occurred while processing the query: tag cannot move under itself.",
+            "Error: An error occurred while processing the query: tag cannot move under itself.",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn rules() {
+    let input = [
+        r#"add "fix outage" #urgent"#,
+        "rule #urgent implies #work",
+        "rule #work implies #office",
+        r#"rule description matches "invoice" => #billing"#,
+        r#"add "send invoice" #urgent/today"#,
+        "rules",
+        "search #office",
+    ];
+
+    let expected = vec![
+        "0",
+        "1 item(s) updated",
+        "1 item(s) updated",
+        "0 item(s) updated",
+        "1",
+        "3 rule(s) found",
+        "#urgent implies #work",
+        "#work implies #office",
+        r#"description matches "invoice" => #billing"#,
+        "2 item(s) found",
+        r#"1 "send invoice" #urgent/today #work #office #billing"#,
+        r#"0 "fix outage" #urgent #work #office"#,
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+}
+
+#[test]
+fn stats() {
+    let input = [
+        r#"add "buy bread" #groceries #shop"#,
+        r#"add "buy milk" #groceries"#,
+        r#"add "bake bread" #home"#,
+        "tags by count limit 2",
+        "tags by name prefix #g",
+        "words by count",
+        "words prefix b by name limit 2",
+        "words limit 99999999999999999999",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "2 term(s) found",
+        "#groceries (2)",
+        "#home (1)",
+        "1 term(s) found",
+        "#groceries (2)",
+        "4 term(s) found",
+        "bread (2)",
+        "buy (2)",
+        "bake (1)",
+        "milk (1)",
+        "2 term(s) found",
+        "bake (1)",
+        "bread (2)",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+}
+
+#[test]
+fn facets() {
+    let input = [
+        r#"add "buy bread" #groceries #bakery"#,
+        r#"add "buy milk" #groceries"#,
+        r#"add "buy nails" #hardware"#,
+        r#"add "call mum" #family"#,
+        "search buy facets",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "3",
+        "3 item(s) found",
+        r#"2 "buy nails" #hardware"#,
+        r#"1 "buy milk" #groceries"#,
+        r#"0 "buy bread" #groceries #bakery"#,
+        "facets: #groceries (2), #bakery (1), #hardware (1)",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+}
+
+#[test]
+fn aggregates() {
+    let input = vec![
+        r#"add "buy bread" #groceries #bakery"#,
+        r#"add "buy milk" #groceries"#,
+        r#"add "buy nails" #hardware"#,
+        "count #groceries",
+        "count buy #none",
+        "search buy group by tag",
+        "done 1",
+        "count #groceries",
+        r#"add "buy jam" #jam #jam"#,
+        "count #jam",
+        "search #jam group by tag",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "2 item(s) found",
+        "3 item(s) found",
+        "3 group(s) found",
+        "#groceries (2)",
+        "#bakery (1)",
+        "#hardware (1)",
+        "done",
+        "1 item(s) found",
+        "3",
+        "1 item(s) found",
+        "1 group(s) found",
+        "#jam (1)",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+
+    let searches = [
+        todo::SearchParams::new(vec![], todo::Tag::from_strings(vec!["jam"])),
+        todo::SearchParams::new(vec![], todo::Tag::from_strings(vec!["groceries"])),
+        todo::SearchParams::new(vec![todo::SearchWord::new("buy")], vec![]),
+    ];
+
+    for sp in searches {
+        assert_eq!(todos.count(sp.clone()), todos.search(sp).len());
+    }
+}
+
+#[test]
+fn explain() {
+    let input = [
+        r#"add "buy fresh bread" #groceries/bakery"#,
+        r#"add "fix the door" #home"#,
+        r#"search brd "fresh bread" /f.x/ #groceries #ho* explain"#,
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2 item(s) found",
+        "1 \"fix the door\" #home",
+        "  #ho* matched #home",
+        "  /f.x/ matched fix",
+        "0 \"buy fresh bread\" #groceries/bakery",
+        "  #groceries matched #groceries/bakery",
+        "  brd matched bread",
+        "  \"fresh bread\" matched fresh bread",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+
+    let params = match todo::parser::query(r#"search bre "fresh bread"~1 #groceries explain"#) {
+        Ok((_, todo::Query::Search(params))) => params,
+        _ => panic!("search should parse"),
+    };
+
+    assert!(params.explain);
+    let iterated = todos.search_iter(params.clone());
+    let indexed = todos.search_with_index(params.clone());
+    assert_eq!(
+        todos.explain(&iterated, &params),
+        todos.explain(&indexed, &params)
+    );
+}
+
+#[test]
+fn highlight() {
+    let input = [
+        r#"add "buy bread" #groceries"#,
+        r#"add "bad brand" #groceries"#,
+        r#"search bd ra highlight"#,
+    ];
+
+    let expected = [
+        "0",
+        "1",
+        "2 item(s) found",
+        "1 \"[b]a[d] [bra]n[d]\" #groceries",
+        "0 \"buy [br]e[ad]\" #groceries",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+
+    let mut out = Vec::new();
+    let options = todo::runner::Options {
+        color: true,
+        ..Default::default()
+    };
+    todo::runner::run_line_with(
+        "search bd highlight",
+        &mut todos,
+        &mut out,
+        &mut err,
+        options,
+    );
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .contains("\x1b[1;31mb\x1b[0mrea\x1b[1;31md\x1b[0m"));
+}
+
+#[test]
+fn corrections() {
+    let input = [
+        r#"add "buy bread" #groceries"#,
+        r#"add "bake bread" #groceries"#,
+        r#"add "fix the broad door" #home"#,
+        "search braed #grocerie",
+        "search zzz",
+    ];
+
+    let expected = [
+        "0",
+        "1",
+        "2",
+        "0 item(s) found",
+        "did you mean: bread, broad, #groceries",
+        "0 item(s) found",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+}
+
+#[test]
+fn suggest() {
+    let input = [
+        r#"add "buy bread" #groceries/bakery"#,
+        r#"add "buy bricks" #home"#,
+        r#"add "bring bread" #groceries"#,
+        "suggest br",
+        "suggest #gro",
+        "suggest #groceries/",
+        "suggest x",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "3 term(s) found",
+        "bread (2)",
+        "bricks (1)",
+        "bring (1)",
+        "2 term(s) found",
+        "#groceries (1)",
+        "#groceries/bakery (1)",
+        "1 term(s) found",
+        "#groceries/bakery (1)",
+        "0 term(s) found",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+
+    assert_eq!(todos.suggest("bu", 1), vec![("buy".to_string(), 2)]);
+}
+
+#[test]
+fn json_format() {
+    let input = [
+        r#"add "buy bread" #groceries"#,
+        "done 0",
+        "done 7",
+        "search bread",
+        "frobnicate",
+        r#"add "Bad Caps""#,
+        "",
+        "count bread",
+    ];
+
+    let expected = [
+        r#"{"item":{"description":"buy bread","done":false,"index":0,"tags":["groceries"]},"type":"added"}"#,
+        r#"{"type":"done"}"#,
+        r#"{"message":"item does not exist","type":"error"}"#,
+        r#"{"items":[],"suggestions":[],"type":"found"}"#,
+        r#"{"message":"could not parse frobnicate","type":"error"}"#,
+        r#"{"message":"could not parse add \"Bad Caps\"","type":"error"}"#,
+        r#"{"count":0,"type":"count"}"#,
+        "",
+    ];
+
+    let options = todo::runner::Options {
+        format: "json".parse().unwrap(),
+        ..Default::default()
+    };
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input.iter().for_each(|line| {
+        todo::runner::run_line_with(line, &mut todos, &mut out, &mut err, options)
+    });
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+}
+
+#[test]
+fn json_rpc() {
+    let input = vec![
+        r##"{"jsonrpc": "2.0", "id": 1, "method": "add", "params": {"description": "buy bread", "tags": ["#groceries"]}}"##,
+        r#"{"jsonrpc": "2.0", "id": "b", "method": "search", "params": {"words": ["brd"], "tags": []}}"#,
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "done", "params": {"index": 7}}"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "done", "params": {"index": "zero"}}"#,
+        r#"{"jsonrpc": "2.0", "id": 5, "method": "undo"}"#,
+        r#"{"jsonrpc": "2.0", "id": 6, "method": "count", "params": {"tags": ["groceries"]}}"#,
+        r#"{"jsonrpc": "2.0", "id": 7, "method": "tag_all", "params": {"tags": ["bakery"], "where": {"words": ["bread"]}}}"#,
+        r#"{"jsonrpc": "2.0", "id": 8, "method": "done_all", "params": {"indices": [0, 3]}}"#,
+        r#"{"jsonrpc": "2.0", "id": 9, "method": "tag_all", "params": {"tags": [""], "where": {}}}"#,
+        r#"{"jsonrpc": "2.0", "id": 10, "method": "rename_tag", "params": {"from": "Bad Tag", "to": "bakery"}}"#,
+        "not json",
+    ];
+
+    let expected = [
+        r#"{"id":1,"jsonrpc":"2.0","result":{"item":{"description":"buy bread","done":false,"index":0,"tags":["groceries"]},"type":"added"}}"#,
+        r#"{"id":"b","jsonrpc":"2.0","result":{"items":[{"description":"buy bread","done":false,"index":0,"tags":["groceries"]}],"suggestions":[],"type":"found"}}"#,
+        r#"{"error":{"code":1,"message":"item does not exist"},"id":3,"jsonrpc":"2.0"}"#,
+        r#"{"error":{"code":-32602,"message":"index must be a non-negative integer"},"id":4,"jsonrpc":"2.0"}"#,
+        r#"{"error":{"code":-32601,"message":"unknown method undo"},"id":5,"jsonrpc":"2.0"}"#,
+        r#"{"id":6,"jsonrpc":"2.0","result":{"count":1,"type":"count"}}"#,
+        r#"{"id":7,"jsonrpc":"2.0","result":{"count":1,"type":"updated"}}"#,
+        r#"{"id":8,"jsonrpc":"2.0","result":{"count":1,"type":"updated"}}"#,
+        r#"{"error":{"code":-32602,"message":"tags must be made of lowercase words separated by /"},"id":9,"jsonrpc":"2.0"}"#,
+        r#"{"error":{"code":-32602,"message":"from must be made of lowercase words separated by /"},"id":10,"jsonrpc":"2.0"}"#,
+    ];
+
+    let options = todo::runner::Options {
+        format: "json-rpc".parse().unwrap(),
+        ..Default::default()
+    };
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input.iter().for_each(|line| {
+        todo::runner::run_line_with(line, &mut todos, &mut out, &mut err, options)
+    });
+    assert!(err.is_empty());
+
+    let out = String::from_utf8(out).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines[..expected.len()], expected[..]);
+    assert!(lines[expected.len()].starts_with(r#"{"error":{"code":-32700,"#));
+    assert!(lines[expected.len()].ends_with(r#""id":null,"jsonrpc":"2.0"}"#));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    let mut todos = todo::TodoList::new();
+    todos.add_rule(todo::Rule::Implies(
+        todo::Tag::new("bakery"),
+        todo::Tag::new("groceries"),
+    ));
+    todos.push(
+        todo::Description::new("buy bread"),
+        todo::Tag::from_strings(vec!["bakery"]),
+    );
+    todos.push(todo::Description::new("fix the door"), vec![]);
+    todos.done_with_index(todo::Index::new(1));
+    for description in ["buy milk", "buy jam"] {
+        todos.push(
+            todo::Description::new(description),
+            todo::Tag::from_strings(vec!["groceries"]),
+        );
+    }
+    todos.done_with_index(todo::Index::new(0));
+
+    let json = serde_json::to_string(&todos).unwrap();
+    assert!(!json.contains("tags_index") && !json.contains("word_index"));
+    let restored: todo::TodoList = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, todos);
+
+    let (_, query) = todo::parser::query(r#"search brd "buy bread"~1 /b.y/ #gro* facets"#).unwrap();
+    let json = serde_json::to_string(&query).unwrap();
+    assert_eq!(serde_json::from_str::<todo::Query>(&json).unwrap(), query);
+    let params = match query {
+        todo::Query::Search(params) => params,
+        _ => panic!("search should parse"),
+    };
+
+    let result = todo::QueryResult::Added(restored.search_iter(params)[0].clone());
+    let json = serde_json::to_string(&result).unwrap();
+    assert_eq!(
+        serde_json::from_str::<todo::QueryResult>(&json).unwrap(),
+        result
+    );
+
+    let skipped = r#"{"items":[{"index":1,"description":"x","tags":[],"done":false}],"rules":[]}"#;
+    assert!(serde_json::from_str::<todo::TodoList>(skipped).is_err());
+}
+
+#[test]
+fn serve() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+    let todos = todo::SharedTodoList::default();
+    let served = todos.clone();
+    std::thread::spawn(move || todo::server::serve_tcp(listener, served, Default::default()));
+
+    let connect = move || {
+        let stream = TcpStream::connect(address).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        (stream, reader)
+    };
+
+    let request = |(stream, reader): &mut (TcpStream, BufReader<TcpStream>), line: &str, lines| {
+        writeln!(stream, "{}", line).unwrap();
+        let mut response = String::new();
+        for _ in 0..lines {
+            reader.read_line(&mut response).unwrap();
+        }
+        response
+    };
+
+    let clients = ["a", "b", "c", "d"]
+        .iter()
+        .map(|client| {
+            std::thread::spawn(move || {
+                let mut connection = connect();
+                for _ in 0..10 {
+                    let line = format!(r#"add "buy bread" #client-{}"#, client);
+                    request(&mut connection, &line, 1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    let mut connection = connect();
+    assert_eq!(
+        request(&mut connection, "count bread", 1),
+        "40 item(s) found\n"
+    );
+    assert_eq!(
+        request(&mut connection, "done 99", 1),
+        "Error: An error occurred while processing the query: item does not exist.\n"
+    );
+
+    // Searches only take the read lock, so they are answered while another reader holds it.
+    todos.read(|_| {
+        assert_eq!(
+            request(&mut connection, "search #client-b group by tag", 2),
+            "1 group(s) found\n#client-b (10)\n"
+        )
+    });
+}
+
+#[test]
+fn http() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+    let todos = todo::SharedTodoList::default();
+    std::thread::spawn(move || todo::http::serve_http(listener, todos));
+
+    let request = |method: &str, target: &str, body: &str| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    };
+
+    let added = request(
+        "POST",
+        "/items",
+        r#"{"description": "buy bread", "tags": ["groceries"]}"#,
+    );
+    assert_eq!(added.0, "HTTP/1.1 201 Created");
+    assert_eq!(
+        added.1,
+        r#"{"item":{"description":"buy bread","done":false,"index":0,"tags":["groceries"]},"type":"added"}"#
+    );
+
+    request("POST", "/items", r#"{"description": "fix the door"}"#);
+    let found = request("GET", "/items?q=brd+door&tag=none", "");
+    assert_eq!(found.0, "HTTP/1.1 200 OK");
+    assert!(found.1.contains(r#""index":1"#) && found.1.contains(r#""index":0"#));
+
+    let found = request("GET", "/items?tag=%23groceries", "");
+    assert!(found.1.contains(r#""index":0"#) && !found.1.contains(r#""index":1"#));
+
+    assert_eq!(request("POST", "/items/0/done", "").0, "HTTP/1.1 200 OK");
+    assert_eq!(
+        request("GET", "/items/0", "").1,
+        r#"{"description":"buy bread","done":true,"index":0,"tags":["groceries"]}"#
+    );
+
+    assert_eq!(request("GET", "/items/7", "").0, "HTTP/1.1 404 Not Found");
+    assert_eq!(
+        request("POST", "/items/7/done", "").0,
+        "HTTP/1.1 404 Not Found"
+    );
+    assert_eq!(request("POST", "/items", "{").0, "HTTP/1.1 400 Bad Request");
+    assert_eq!(
+        request("GET", "/items?q=buy%20$$", "").0,
+        "HTTP/1.1 400 Bad Request"
+    );
+    assert_eq!(
+        request("POST", "/items", "{}").0,
+        "HTTP/1.1 400 Bad Request"
+    );
+    assert_eq!(
+        request("DELETE", "/items/0", "").0,
+        "HTTP/1.1 405 Method Not Allowed"
+    );
+}
+
+#[test]
+fn changes() {
+    use todo::ChangeKind::*;
+
+    let mut todos = todo::TodoList::new();
+    todos.push(todo::Description::new("buy bread"), vec![]);
+    todos.push(
+        todo::Description::new("buy milk"),
+        todo::Tag::from_strings(vec!["dairy"]),
+    );
+    todos.done_with_index(todo::Index::new(0));
+    todos.rename_tag(&todo::Tag::new("dairy"), &todo::Tag::new("milk"));
+    todos.add_rule(todo::Rule::Implies(
+        todo::Tag::new("milk"),
+        todo::Tag::new("groceries"),
+    ));
+
+    let changes = todos.changes_since(0).unwrap();
+    let kinds = changes
+        .iter()
+        .map(|change| (change.seq, change.kind, change.item.index.value()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        kinds,
+        vec![
+            (1, Added, 0),
+            (2, Added, 1),
+            (3, Done, 0),
+            (4, Edited, 1),
+            (5, Edited, 1)
+        ]
+    );
+    assert_eq!(
+        changes[4].item.tags,
+        todo::Tag::from_strings(vec!["milk", "groceries"])
+    );
+    assert_eq!(todos.changes_since(5), Some(vec![]));
+    assert_eq!(todos.changes_since(6), None);
+
+    for _ in 0..2000 {
+        todos.push(todo::Description::new("buy more"), vec![]);
+    }
+
+    assert_eq!(todos.last_change(), 2005);
+    assert_eq!(todos.changes_since(0), None);
+    assert_eq!(todos.changes_since(2004).unwrap().len(), 1);
+}
+
+#[test]
+fn events() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+    let todos = todo::SharedTodoList::default();
+    std::thread::spawn(move || todo::http::serve_http(listener, todos));
+
+    let add = |description: &str| {
+        let body = format!(r#"{{"description": "{}"}}"#, description);
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST /items HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        stream.read_to_string(&mut String::new()).unwrap();
+    };
+
+    let subscribe = |head: &str| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET /events{}\r\n\r\n", head).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        assert_eq!(status, "HTTP/1.1 200 OK\r\n");
+        let mut header = String::new();
+        while header != "\r\n" {
+            header.clear();
+            reader.read_line(&mut header).unwrap();
+        }
+        reader
+    };
+
+    let event = |reader: &mut BufReader<TcpStream>| {
+        let mut event = String::new();
+        while !event.ends_with("\n\n") {
+            reader.read_line(&mut event).unwrap();
+        }
+        event
+    };
+
+    add("buy bread");
+    add("buy milk");
+
+    let mut all = subscribe("?since=0 HTTP/1.1");
+    assert_eq!(
+        event(&mut all),
+        concat!(
+            "id: 1\nevent: added\n",
+            r#"data: {"item":{"description":"buy bread","done":false,"index":0,"tags":[]},"seq":1,"type":"added"}"#,
+            "\n\n"
+        )
+    );
+    assert!(event(&mut all).starts_with("id: 2\nevent: added\n"));
+
+    let mut resumed = subscribe(" HTTP/1.1\r\nLast-Event-ID: 1");
+    assert!(event(&mut resumed).starts_with("id: 2\nevent: added\n"));
+
+    add("buy eggs");
+    assert!(event(&mut all).starts_with("id: 3\nevent: added\n"));
+    assert!(event(&mut resumed).starts_with("id: 3\nevent: added\n"));
+
+    let mut reset = subscribe("?since=9 HTTP/1.1");
+    assert_eq!(
+        event(&mut reset),
+        "id: 3\nevent: reset\ndata: {\"seq\":3}\n\n"
+    );
+}
+
+#[test]
+fn shared() {
+    let todos = todo::SharedTodoList::default();
+    let tag_search =
+        |tag: &str| todo::SearchParams::new(vec![], todo::Tag::from_strings(vec![tag]));
+
+    let writers = ["a", "b", "c", "d"]
+        .iter()
+        .map(|writer| {
+            let todos = todos.clone();
+            std::thread::spawn(move || {
+                for _ in 0..250 {
+                    let tags = todo::Tag::from_strings(vec![writer]);
+                    let item = todos.push(todo::Description::new("buy bread"), tags);
+                    if *writer == "d" {
+                        todos.done_with_index(item.index).unwrap();
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let readers = (0..4)
+        .map(|_| {
+            let todos = todos.clone();
+            std::thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..200 {
+                    let found = todos.search(tag_search("a"));
+                    assert!(found.len() >= last);
+                    assert!(found
+                        .iter()
+                        .all(|item| !item.done && item.tags == vec![todo::Tag::new("a")]));
+                    last = found.len();
+
+                    let done = todos.search(tag_search("d"));
+                    assert!(done.len() <= 1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in writers.into_iter().chain(readers) {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(todos.count(tag_search("a")), 250);
+    assert_eq!(todos.count(tag_search("d")), 0);
+    let words = todo::SearchParams::new(vec![todo::SearchWord::new("bread")], vec![]);
+    assert_eq!(todos.count(words), 750);
+    assert_eq!(
+        todos
+            .get(todo::Index::new(999))
+            .map(|item| item.index.value()),
+        Some(999)
+    );
+}
+
+#[test]
+fn snapshot() {
+    let mut tl = todo::TodoList::new();
+    for _ in 0..3 {
+        tl.push(
+            todo::Description::new("buy bread"),
+            todo::Tag::from_strings(vec!["groceries"]),
+        );
+    }
+
+    let snapshot = tl.snapshot();
+    tl.done_with_index(todo::Index::new(0)).unwrap();
+    tl.push(
+        todo::Description::new("bake bread"),
+        todo::Tag::from_strings(vec!["kitchen"]),
+    );
+    tl.rename_tag(&todo::Tag::new("groceries"), &todo::Tag::new("shop"));
+
+    let bread = || todo::SearchParams::new(vec![todo::SearchWord::new("bread")], vec![]);
+    let groceries = || todo::SearchParams::new(vec![], todo::Tag::from_strings(vec!["groceries"]));
+    assert_eq!(snapshot.count(bread()), 3);
+    assert_eq!(snapshot.count(groceries()), 3);
+    assert_eq!(snapshot.last_change(), 3);
+    assert!(snapshot.get(todo::Index::new(3)).is_none());
+    assert_eq!(tl.count(bread()), 3);
+    assert_eq!(tl.count(groceries()), 0);
+
+    let todos = todo::SharedTodoList::new(tl);
+    let snapshot = todos.snapshot();
+    let writer = {
+        let todos = todos.clone();
+        std::thread::spawn(move || {
+            for _ in 0..100 {
+                let item = todos.push(todo::Description::new("slice bread"), vec![]);
+                todos.done_with_index(item.index).unwrap();
+            }
+        })
+    };
+
+    for _ in 0..100 {
+        assert_eq!(snapshot.count(bread()), 3);
+    }
+
+    writer.join().unwrap();
+    assert_eq!(snapshot.count(bread()), 3);
+    assert_eq!(todos.count(bread()), 3);
+    assert_eq!(todos.snapshot().last_change(), snapshot.last_change() + 200);
+}
+
+#[test]
+fn transactions() {
+    let input = vec![
+        r#"add "buy milk" #groceries"#,
+        "begin",
+        r#"add "buy bread" #groceries"#,
+        "done 0",
+        "search #groceries",
+        "commit",
+        "search #groceries",
+        "begin",
+        r#"add "buy eggs" #groceries"#,
+        "done 7",
+        "commit",
+        "search #groceries",
+        "begin",
+        "rename-tag #groceries #shop",
+        "rollback",
+        "rollback",
+        "tags",
+    ];
+
+    let expected = vec![
+        "0",
+        "0 change(s) staged",
+        "1 change(s) staged",
+        "2 change(s) staged",
+        "1 item(s) found",
+        r#"0 "buy milk" #groceries"#,
+        "2 change(s) committed",
+        "1 item(s) found",
+        r#"1 "buy bread" #groceries"#,
+        "0 change(s) staged",
+        "1 change(s) staged",
+        "2 change(s) staged",
+        "1 item(s) found",
+        r#"1 "buy bread" #groceries"#,
+        "0 change(s) staged",
+        "1 change(s) staged",
+        "1 change(s) rolled back",
+        "1 tag(s) found",
+        "#groceries (1)",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut session = todo::runner::Session::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    for line in input {
+        let options = Default::default();
+        todo::runner::run_session_line(line, &mut todos, &mut session, &mut out, &mut err, options);
+    }
+
+    assert!(!session.in_transaction());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+    assert_eq!(
+        String::from_utf8(err).unwrap(),
+        [
+            "Error: An error occurred while processing the query: \
+             transaction rolled back, item does not exist.",
+            "Error: An error occurred while processing the query: no transaction begun.",
+            "",
+        ]
+        .join("\n")
+    );
+
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    todo::runner::run_line("begin", &mut todos, &mut out, &mut err);
+    assert!(out.is_empty());
+    assert!(String::from_utf8(err)
+        .unwrap()
+        .contains("transactions need a session"));
+
+    let todos = todo::SharedTodoList::new(todos);
+    let mut writer = todo::runner::Session::new();
+    let mut reader = todo::runner::Session::new();
+    let run = |line: &str, session: &mut todo::runner::Session| {
+        let mut out = Vec::new();
+        let options = Default::default();
+        todo::runner::run_shared_line(line, &todos, session, &mut out, &mut Vec::new(), options);
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(run("begin", &mut writer), "0 change(s) staged\n");
+    assert_eq!(
+        run(r#"add "bake bread" #kitchen"#, &mut writer),
+        "1 change(s) staged\n"
+    );
+    assert_eq!(run("count #kitchen", &mut reader), "0 item(s) found\n");
+    assert_eq!(run(r#"add "buy jam" #groceries"#, &mut reader), "2\n");
+    assert_eq!(run("commit", &mut writer), "1 change(s) committed\n");
+    assert_eq!(
+        run("search #kitchen", &mut reader),
+        "1 item(s) found\n3 \"bake bread\" #kitchen\n"
+    );
+    assert!(todos.get(todo::Index::new(3)).is_some());
+}
+
+#[test]
+fn bulk() {
+    let input = vec![
+        r#"add "buy milk" #groceries"#,
+        r#"add "buy bread" #groceries"#,
+        r#"add "buy eggs" #groceries"#,
+        r#"add "fix the door" #home"#,
+        r#"add "paint the door" #home"#,
+        r#"add "call mum""#,
+        "rule #urgent implies #today",
+        "done 0 2 9",
+        "done 1..2",
+        "tag #urgent where door",
+        "search #today",
+        "untag #home 3..4",
+        "tag #errand where #groceries",
+        "done where #urgent",
+        "count #urgent",
+        "done 5..",
+        "done 5 foo",
+        "done 5..99999999999999999999",
+        "done 99999999999999999999",
+        "count mum",
+        "done 5",
+    ];
+
+    let expected = vec![
+        "0",
+        "1",
+        "2",
+        "3",
+        "4",
+        "5",
+        "0 item(s) updated",
+        "2 item(s) updated",
+        "1 item(s) updated",
+        "2 item(s) updated",
+        "2 item(s) found",
+        r#"4 "paint the door" #home #urgent #today"#,
+        r#"3 "fix the door" #home #urgent #today"#,
+        "2 item(s) updated",
+        "0 item(s) updated",
+        "2 item(s) updated",
+        "0 item(s) found",
+        "1 item(s) found",
+        "done",
+        "",
+    ];
+
+    let mut todos = todo::TodoList::new();
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    input
+        .iter()
+        .for_each(|line| todo::runner::run_line(line, &mut todos, &mut out, &mut err));
+    assert!(err.is_empty());
+    assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n"));
+    assert_eq!(
+        todos.get(todo::Index::new(3)).unwrap().tags,
+        todo::Tag::from_strings(vec!["urgent", "today"])
+    );
+}
+
 enum Query {
     Add(QueryAdd),
     Done(QueryDone),
@@ -214,13 +1493,7 @@ impl Queries {
             .enumerate()
             .filter_map(|(idx, query)| {
                 if let Query::Add(ref query) = query {
-                    let todo = todo::TodoItem::new(
-                        todo::Index::new(idx as u64),
-                        query.description.clone(),
-                        query.tags.clone(),
-                        false,
-                    );
-                    Some((idx, todo))
+                    Some((idx, query.item(idx as u64)))
                 } else {
                     None
                 }
@@ -232,12 +1505,7 @@ impl Queries {
 
     pub fn push(&mut self, query: Query) {
         if let Query::Add(ref query) = query {
-            let item = todo::TodoItem::new(
-                todo::Index::new(self.todos.len() as u64),
-                query.description.clone(),
-                query.tags.clone(),
-                false,
-            );
+            let item = query.item(self.todos.len() as u64);
             self.todos.push((self.queries.len(), item));
         }
 
@@ -412,6 +1680,24 @@ impl QueryAdd {
         }
     }
 
+    /// # Returns
+    /// The item the query adds at the index, keeping repeated tags once as `add` does.
+    fn item(&self, index: u64) -> todo::TodoItem {
+        let mut tags: Vec<todo::Tag> = vec![];
+        for tag in self.tags.iter() {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        todo::TodoItem::new(
+            todo::Index::new(index),
+            self.description.clone(),
+            tags,
+            false,
+        )
+    }
+
     /// # Returns
     /// A search word that matches the associated item's description.
     fn search_word(&self, rng: &mut impl Rng) -> String {